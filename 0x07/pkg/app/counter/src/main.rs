@@ -5,6 +5,7 @@
 
 use lib::*;
 use lib::sync::Semaphore;
+use lib::vec::Vec;
 
 extern crate lib;
 
@@ -15,25 +16,19 @@ static SEM:Semaphore = Semaphore::new(0);
 
 fn main() -> isize {
     lib::init();
-    let mut pids = [0u16; THREAD_COUNT];
     SEM.init(1);
-    for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
-        if pid == 0 {
-            do_counter_inc();
-            sys_exit(0);
-        } else {
-            pids[i] = pid; // only parent knows child's pid
-        }
-    }
+    let threads: Vec<_> = (0..THREAD_COUNT)
+        .map(|_| thread::spawn(do_counter_inc))
+        .collect();
 
     let cpid = sys_get_pid();
-    println!("process #{} holds threads: {:?}", cpid, &pids);
+    let tids: Vec<_> = threads.iter().map(|thread| thread.tid()).collect();
+    println!("process #{} holds threads: {:?}", cpid, &tids);
     sys_stat();
 
-    for i in 0..THREAD_COUNT {
-        println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+    for thread in threads {
+        println!("#{} waiting for #{}...", cpid, thread.tid());
+        thread.join().ok();
     }
 
     SEM.remove();
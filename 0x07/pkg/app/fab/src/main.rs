@@ -15,7 +15,14 @@ fn main() -> isize {
 
     lib::init();
     // prase input as u64
-    let n = 100;
+    let n = match args().nth(1).map(str::parse::<u64>) {
+        None => 100,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            println!("usage: <n>, n must be a number");
+            return 1;
+        }
+    };
 
     if n > 1000000 {
         println!("n must be less than 1000000");
@@ -18,7 +18,14 @@ fn factorial(n: u64) -> u64 {
 fn main() -> isize {
     lib::init();
     // prase input as u64
-    let n = 1000000;
+    let n = match args().nth(1).map(str::parse::<u64>) {
+        None => 1000000,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            println!("usage: <n>, n must be a number");
+            return 1;
+        }
+    };
 
     if n > 1000000 {
         println!("n must be less than 1000000");
@@ -34,7 +41,6 @@ fn main() -> isize {
     // print result
     println!("The factorial of {} under modulo {} is {}.", n, MOD, result);
 
-    sys_wait_pid(1);
     0
 }
 
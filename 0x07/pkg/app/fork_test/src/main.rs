@@ -13,9 +13,8 @@ fn main() -> isize {
     let mut c = 32;
     let m_ptr = &raw mut M;
 
-    // do not alloc heap before `fork`
-    // which may cause unexpected behavior since we won't copy the heap in `fork`
-    let pid = sys_fork();
+    // the child gets a copy-on-write copy of the whole address space
+    let pid = sys_fork().expect("Failed to fork");
 
     if pid == 0 {
         println!("I am the child process");
@@ -38,7 +37,7 @@ fn main() -> isize {
 
         println!("Waiting for child to exit...");
 
-        let ret = sys_wait_pid(pid);
+        let ret = sys_wait_pid(pid).expect("Failed to wait for child");
 
         println!("Child exited with status {}", ret);
 
@@ -46,7 +45,8 @@ fn main() -> isize {
 
         unsafe {
             println!("parent read value of M: {:#x}", *m_ptr);
-            assert_eq!(*m_ptr, 0x2333);
+            // the write of the child went to its own copy
+            assert_eq!(*m_ptr, 0xdeadbeef);
         }
 
         c += 1024;
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+use lib::{string::String, *};
+
+extern crate lib;
+
+const USAGE: &str = "usage: ls [-l] [-S | -t] [-r] [path]";
+
+fn main() -> isize {
+    lib::init();
+
+    let mut long = false;
+    let mut by_size = false;
+    let mut by_time = false;
+    let mut reverse = false;
+    let mut path = "/";
+    for arg in args().skip(1) {
+        match arg.strip_prefix('-') {
+            Some(flags) => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => long = true,
+                        'S' => by_size = true,
+                        't' => by_time = true,
+                        'r' => reverse = true,
+                        _ => {
+                            println!("{}", USAGE);
+                            return 1;
+                        }
+                    }
+                }
+            }
+            None => path = arg,
+        }
+    }
+
+    let mut entries = match read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("ls: {}: {}", path, err);
+            return 1;
+        }
+    };
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    // largest or newest first, like ls does, ties stay by name
+    if by_size {
+        entries.sort_by(|a, b| b.size.cmp(&a.size));
+    } else if by_time {
+        entries.sort_by(|a, b| b.modified.cmp(&a.modified));
+    }
+    if reverse {
+        entries.reverse();
+    }
+
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        if long {
+            let kind = if entry.is_dir { 'd' } else { '-' };
+            println!(
+                "{} {:>9} {:<19} {}{}",
+                kind,
+                format_size(entry.size),
+                entry.modified.map(format_time).unwrap_or_else(|| "-".into()),
+                entry.name,
+                suffix
+            );
+        } else {
+            println!("{}{}", entry.name, suffix);
+        }
+    }
+
+    0
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", size, UNITS[0]),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// `YYYY-MM-DD hh:mm:ss` of `secs` since the Unix epoch, in UTC
+fn format_time(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+entry!(main);
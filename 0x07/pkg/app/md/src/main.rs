@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use lib::{sync::Semaphore, *};
+use lib::{sync::Semaphore, vec::Vec, *};
 
 extern crate lib;
 
@@ -27,42 +27,36 @@ static WRITE_MUTEX: Semaphore = Semaphore::new(2);
 
 fn main() -> isize {
     lib::init();
-    let mut pids = [0u16; THREAD_COUNT];
     EMPTY.init(MAX_MESSAGE_COUNT);
     FULL.init(0);
     WRITE_MUTEX.init(1);
 
-    for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
-
-        if i < THREAD_COUNT / 2 {
-            if pid == 0 {
-                for j in 0..MAX_MESSAGE_COUNT {
-                    write_message(i + j);
-                }
-                sys_exit(0);
-            } else {
-                pids[i] = pid;
-            }
-        } else {
-            if pid == 0 {
-                for _ in 0..MAX_MESSAGE_COUNT {
-                    read_message();
-                }
-                sys_exit(0);
+    let threads: Vec<_> = (0..THREAD_COUNT)
+        .map(|i| {
+            if i < THREAD_COUNT / 2 {
+                thread::spawn(move || {
+                    for j in 0..MAX_MESSAGE_COUNT {
+                        write_message(i + j);
+                    }
+                })
             } else {
-                pids[i] = pid;
+                thread::spawn(|| {
+                    for _ in 0..MAX_MESSAGE_COUNT {
+                        read_message();
+                    }
+                })
             }
-        }
-    }
+        })
+        .collect();
 
     let cpid = sys_get_pid();
-    println!("process #{} holds threads: {:?}", cpid, &pids);
+    let tids: Vec<_> = threads.iter().map(|thread| thread.tid()).collect();
+    println!("process #{} holds threads: {:?}", cpid, &tids);
     sys_stat();
 
-    for i in 0..THREAD_COUNT {
-        println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+    for thread in threads {
+        println!("#{} waiting for #{}...", cpid, thread.tid());
+        thread.join().ok();
     }
 
     println!("Message Queue: {:?}", unsafe { MQ.queue });
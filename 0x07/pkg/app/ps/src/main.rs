@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+use lib::{string::String, vec::Vec, *};
+
+extern crate lib;
+
+const USAGE: &str = "usage: ps [-T] [-H]";
+
+fn main() -> isize {
+    lib::init();
+
+    let mut threads = false;
+    let mut tree = false;
+    for arg in args().skip(1) {
+        match arg {
+            "-T" => threads = true,
+            "-H" => tree = true,
+            _ => {
+                println!("{}", USAGE);
+                return 1;
+            }
+        }
+    }
+
+    let mut procs = match sys_list_proc() {
+        Ok(procs) => procs,
+        Err(err) => {
+            println!("ps: {}", err);
+            return 1;
+        }
+    };
+    if !threads {
+        procs.retain(|p| p.pid == p.tgid);
+    }
+
+    println!(
+        "{:>5} {:>5} {:>5} {:>5} {:4} {:>3} {:>8} {:>9} {:>5}  NAME",
+        "PID", "TGID", "PPID", "PGID", "STAT", "NI", "TIME", "MEM", "EXIT"
+    );
+    if tree {
+        // roots are the processes whose parent is not listed
+        let mut roots: Vec<&ProcInfo> = procs
+            .iter()
+            .filter(|p| !procs.iter().any(|parent| parent.pid == p.ppid))
+            .collect();
+        roots.sort_by_key(|p| p.pid);
+        for root in roots {
+            print_tree(&procs, root, 0);
+        }
+    } else {
+        for p in &procs {
+            print_proc(p, 0);
+        }
+    }
+
+    0
+}
+
+fn print_tree(procs: &[ProcInfo], proc: &ProcInfo, depth: usize) {
+    print_proc(proc, depth);
+    for child in procs.iter().filter(|p| p.ppid == proc.pid && p.pid != proc.pid) {
+        print_tree(procs, child, depth + 1);
+    }
+}
+
+fn print_proc(p: &ProcInfo, depth: usize) {
+    let stat = match p.status() {
+        Some(ProcStatus::Running) | Some(ProcStatus::Ready) => "R",
+        Some(ProcStatus::Blocked) => "S",
+        Some(ProcStatus::Stopped) => "T",
+        Some(ProcStatus::Dead) => "Z",
+        None => "?",
+    };
+    let exit = p.exit_code().map_or(String::from("-"), |code| format!("{}", code));
+    let indent = if depth == 0 {
+        String::new()
+    } else {
+        format!("{}\\_ ", "   ".repeat(depth - 1))
+    };
+    println!(
+        "{:>5} {:>5} {:>5} {:>5} {:4} {:>3} {:>8} {:>7}Ki {:>5}  {}{}",
+        p.pid,
+        p.tgid,
+        p.ppid,
+        p.pgid,
+        stat,
+        p.nice,
+        p.user_ticks + p.system_ticks,
+        p.memory / 1024,
+        exit,
+        indent,
+        p.name()
+    );
+}
+
+entry!(main);
@@ -6,6 +6,10 @@ use lib::*;
 
 fn main() -> isize {
     lib::init();
+    // Ctrl-C is meant for the programs we run, not for the shell
+    sys_signal(Signal::Interrupt, SigHandler::Ignore).ok();
+    sys_set_sid().ok();
+    sys_set_foreground(sys_get_pid()).ok();
     print!("\x1B[2J\x1B[H");
     const RESET: &str = "\x1b[0m";
     const BOLD: &str = "\x1b[1m";
@@ -96,6 +100,10 @@ fn main() -> isize {
     println!("{RESET}\n");
     
     loop {
+        // background jobs that finished since the last command
+        while let Ok(Some((pid, ret))) = sys_wait_child(None, WNOHANG) {
+            println!("{BOLD}{R3}✓ [{}] done, exited with {}{RESET}", pid, ret);
+        }
 
         print!("{DIM}▓▒░{RESET} {BOLD}{R3}[YatSenOS]{R4}> {RESET}");
 
@@ -110,8 +118,21 @@ fn main() -> isize {
 
                 let commands = [
                     ("la", "列出所有可用应用"),
-                    ("run <路径>", "运行指定路径的应用程序"),
-                    ("ps", "显示系统状态"),
+                    ("run <路径> [参数...] [&]", "运行指定路径的应用程序, 以 & 结尾则在后台运行"),
+                    ("exec <路径> [参数...]", "用指定应用替换当前终端"),
+                    ("ps [-T] [-H]", "列出进程, -T 包含线程, -H 按父子关系缩进"),
+                    ("stat", "显示系统状态"),
+                    ("ls [-l] [-S|-t] [-r] [路径]", "列出目录内容, -l 显示详细信息, 按大小/修改时间排序"),
+                    ("cat <路径>", "输出文件内容, 如 /proc/<进程号>/status"),
+                    ("strace [-b] <路径> [参数...]", "跟踪应用的系统调用, -b 写入内核缓冲区"),
+                    ("tracelog", "输出内核跟踪缓冲区"),
+                    ("kill <进程号> [信号]", "向进程发送信号, 默认为 15 (SIGTERM)"),
+                    ("renice <进程号> <优先级>", "设置自身或子孙进程的 nice 值 (-20 ~ 19), 越小越优先, 只能调大"),
+                    ("time <应用> [参数...]", "运行应用并统计 CPU 时间、上下文切换、缺页和系统调用次数"),
+                    ("ulimit [资源] [软上限] [硬上限]", "查看或设置资源上限 (cpu/heap/stack/rss/nofile), 硬上限只能调小, 之后运行的应用会继承"),
+                    ("env", "列出环境变量"),
+                    ("export <名称>=<值>", "设置环境变量, 之后运行的应用会继承"),
+                    ("unset <名称>", "删除环境变量"),
                     ("clear", "清屏"),
                     ("exit", "退出终端")
                 ];
@@ -135,19 +156,211 @@ fn main() -> isize {
             "run" => {
                 let path = command.next().unwrap();
                 let name: vec::Vec<&str> = path.rsplit('/').collect();
-                let pid = sys_spawn(path);
-                if pid == 0 {
-                    println!("{BOLD}{R1}⚠ Failed to run app: {}{RESET}", name[0]);
-                    continue;
+                let mut args: vec::Vec<&str> = core::iter::once(path)
+                    .chain(command.filter(|arg| !arg.is_empty()))
+                    .collect();
+                let background = args.len() > 1 && args.last() == Some(&"&");
+                if background {
+                    args.pop();
+                }
+                match sys_spawn(path, &args, &[]) {
+                    Err(err) => {
+                        println!("{BOLD}{R1}⚠ Failed to run app: {}: {}{RESET}", name[0], err);
+                        continue;
+                    }
+                    // in a group of its own, so it neither gets Ctrl-C nor
+                    // reads the console
+                    Ok(pid) if background => {
+                        sys_set_pgid(pid, pid).ok();
+                        println!("{BOLD}{R3}[{}] {}{RESET}", pid, name[0]);
+                    }
+                    Ok(pid) => {
+                        sys_stat();
+                        match wait_foreground(pid) {
+                            Ok(ret) => println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], ret),
+                            Err(err) => println!("{BOLD}{R1}⚠ Failed to wait for {}: {}{RESET}", name[0], err),
+                        }
+                    }
+                }
+            }
+            "exec" => {
+                let path = command.next().unwrap_or("");
+                let args: vec::Vec<&str> = core::iter::once(path)
+                    .chain(command.filter(|arg| !arg.is_empty()))
+                    .collect();
+                // the new image should be interruptible again
+                sys_signal(Signal::Interrupt, SigHandler::Default).ok();
+                let err = sys_exec(path, &args, &[]);
+                sys_signal(Signal::Interrupt, SigHandler::Ignore).ok();
+                println!("{BOLD}{R1}⚠ exec: {}: {}{RESET}", path, err);
+            }
+            "strace" => {
+                let mut path = command.next().unwrap_or("");
+                let mode = if path == "-b" {
+                    path = command.next().unwrap_or("");
+                    TraceMode::Buffer
                 } else {
-                    sys_stat();
-                    println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], sys_wait_pid(pid));
+                    TraceMode::Console
+                };
+                let name: vec::Vec<&str> = path.rsplit('/').collect();
+                // the child inherits the mode, so only trace ourselves around spawn
+                if let Err(err) = sys_trace(0, mode) {
+                    println!("{BOLD}{R1}⚠ strace: {}{RESET}", err);
+                    continue;
+                }
+                let args: vec::Vec<&str> = core::iter::once(path)
+                    .chain(command.filter(|arg| !arg.is_empty()))
+                    .collect();
+                let ret = sys_spawn(path, &args, &[]);
+                sys_trace(0, TraceMode::Off).ok();
+                match ret {
+                    Err(err) => println!("{BOLD}{R1}⚠ Failed to run app: {}: {}{RESET}", name[0], err),
+                    Ok(pid) => match wait_foreground(pid) {
+                        Ok(ret) => println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], ret),
+                        Err(err) => println!("{BOLD}{R1}⚠ Failed to wait for {}: {}{RESET}", name[0], err),
+                    },
+                }
+            }
+            "time" => {
+                let path = command.next().unwrap_or("");
+                let name: vec::Vec<&str> = path.rsplit('/').collect();
+                let args: vec::Vec<&str> = core::iter::once(path)
+                    .chain(command.filter(|arg| !arg.is_empty()))
+                    .collect();
+                // the child counts once it has been waited for
+                let before = sys_get_rusage(RusageWho::Children).unwrap_or_default();
+                let ret = match sys_spawn(path, &args, &[]) {
+                    Err(err) => {
+                        println!("{BOLD}{R1}⚠ Failed to run app: {}: {}{RESET}", name[0], err);
+                        continue;
+                    }
+                    Ok(pid) => wait_foreground(pid),
+                };
+                let after = sys_get_rusage(RusageWho::Children).unwrap_or_default();
+                match ret {
+                    Ok(ret) => println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], ret),
+                    Err(err) => println!("{BOLD}{R1}⚠ Failed to wait for {}: {}{RESET}", name[0], err),
+                }
+                let usage = after - before;
+                println!(
+                    "user {} ticks, sys {} ticks, {} voluntary / {} involuntary switches, {} page faults, {} syscalls",
+                    usage.user_ticks,
+                    usage.system_ticks,
+                    usage.voluntary_switches,
+                    usage.involuntary_switches,
+                    usage.page_faults,
+                    usage.syscalls
+                );
+            }
+            "tracelog" => {
+                let buf = &mut [0u8; 1024];
+                while let Ok(len) = sys_read_trace(buf) {
+                    if len == 0 {
+                        break;
+                    }
+                    print!("{}", string::String::from_utf8_lossy(&buf[..len]));
+                }
+            }
+            "kill" => {
+                let pid = command.next().and_then(|pid| pid.parse::<u16>().ok());
+                let sig = match command.next() {
+                    None => Ok(Signal::Terminate),
+                    Some(sig) => sig
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|sig| Signal::try_from(sig).ok())
+                        .ok_or(Errno::InvalidArgument),
+                };
+                match (pid, sig) {
+                    (Some(pid), Ok(sig)) => {
+                        if let Err(err) = sys_kill(pid, sig) {
+                            println!("{BOLD}{R1}⚠ kill: {}: {}{RESET}", pid, err);
+                        }
+                    }
+                    _ => println!("{BOLD}{R1}⚠ usage: kill <pid> [signal]{RESET}"),
+                }
+            }
+            "renice" => {
+                let pid = command.next().and_then(|pid| pid.parse::<u16>().ok());
+                let nice = command.next().and_then(|nice| nice.parse::<i8>().ok());
+                match (pid, nice) {
+                    (Some(pid), Some(nice)) => match sys_set_priority(pid, nice) {
+                        Ok(()) => {
+                            let nice = sys_get_priority(pid).unwrap_or(nice);
+                            println!("{BOLD}{R3}✓ {}: nice {}{RESET}", pid, nice);
+                        }
+                        Err(err) => println!("{BOLD}{R1}⚠ renice: {}: {}{RESET}", pid, err),
+                    },
+                    _ => println!("{BOLD}{R1}⚠ usage: renice <pid> <nice>{RESET}"),
+                }
+            }
+            "ulimit" => match (command.next(), command.next(), command.next()) {
+                (None, _, _) => {
+                    println!("{:<8}{:<12}{}", "", "soft", "hard");
+                    for (name, res) in RLIMITS {
+                        match sys_get_rlimit(res) {
+                            Ok(value) => println!(
+                                "{:<8}{:<12}{}",
+                                name,
+                                limit_str(value.soft),
+                                limit_str(value.hard)
+                            ),
+                            Err(err) => println!("{BOLD}{R1}⚠ ulimit: {}: {}{RESET}", name, err),
+                        }
+                    }
+                }
+                (Some(name), soft, hard) => {
+                    let res = RLIMITS.iter().find(|(n, _)| *n == name).map(|(_, res)| *res);
+                    match (res, soft.and_then(parse_limit)) {
+                        (Some(res), Some(soft)) => {
+                            // the hard limit stays as it is unless given
+                            let value = match hard {
+                                Some(hard) => parse_limit(hard)
+                                    .map(|hard| RlimitValue { soft, hard })
+                                    .ok_or(Errno::InvalidArgument),
+                                None => sys_get_rlimit(res).map(|value| RlimitValue { soft, ..value }),
+                            };
+                            if let Err(err) = value.and_then(|value| sys_set_rlimit(res, value)) {
+                                println!("{BOLD}{R1}⚠ ulimit: {}: {}{RESET}", name, err);
+                            }
+                        }
+                        _ => println!(
+                            "{BOLD}{R1}⚠ usage: ulimit [cpu|heap|stack|rss|nofile] [soft|unlimited] [hard|unlimited]{RESET}"
+                        ),
+                    }
+                }
+            },
+            "env" => match sys_list_env() {
+                Ok(vars) => {
+                    for (key, val) in vars {
+                        println!("{}={}", key, val);
+                    }
+                }
+                Err(err) => println!("{BOLD}{R1}⚠ env: {}{RESET}", err),
+            },
+            "export" => {
+                let var = command.next().unwrap_or("");
+                let (key, val) = var.split_once('=').unwrap_or((var, ""));
+                if let Err(err) = sys_set_env(key, val) {
+                    println!("{BOLD}{R1}⚠ export: {}: {}{RESET}", var, err);
                 }
             }
-            "ps" => {
+            "unset" => {
+                let key = command.next().unwrap_or("");
+                if let Err(err) = sys_unset_env(key) {
+                    println!("{BOLD}{R1}⚠ unset: {}: {}{RESET}", key, err);
+                }
+            }
+            "stat" => {
                 println!("{BOLD}{R4}〓〓〓 系统状态 〓〓〓{RESET}");
                 sys_stat();
             }
+            // listing and formatting are up to the apps
+            "ps" | "ls" => {
+                if let Err(err) = run_tool(op, command) {
+                    println!("{BOLD}{R1}⚠ {}: {}{RESET}", op, err);
+                }
+            }
             "exit" => {
                 let goodbye = "Goodbye! See you next time!";
                 for (i, ch) in goodbye.chars().enumerate() {
@@ -158,18 +371,32 @@ fn main() -> isize {
                 println!("{RESET}");
                 break;
             }
-            "ls" =>{
-                  sys_list_dir(command.next().unwrap_or("/"));
-            }
             "cat" => {
-                let fd = sys_open_file(command.next().unwrap_or(""));
+                let path = command.next().unwrap_or("");
+                let fd = match sys_open_file(path) {
+                    Ok(fd) => fd,
+                    Err(err) => {
+                        println!("{BOLD}{R1}⚠ cat: {}: {}{RESET}", path, err);
+                        continue;
+                    }
+                };
+                // files in /proc may be larger than one buffer
                 let buf = &mut [0u8; 1024];
-                sys_read(fd, buf);
-                println!(
-                    "{}",
-                    core::str::from_utf8(buf).unwrap_or("Failed to read file")
-                );
-                sys_close_file(fd);
+                loop {
+                    match sys_read(fd, buf) {
+                        Ok(0) => break,
+                        Ok(len) => print!(
+                            "{}",
+                            core::str::from_utf8(&buf[..len]).unwrap_or("Failed to read file")
+                        ),
+                        Err(err) => {
+                            println!("{BOLD}{R1}⚠ cat: {}: {}{RESET}", path, err);
+                            break;
+                        }
+                    }
+                }
+                println!();
+                sys_close_file(fd).ok();
             }
             "clear" => {
                 print!("\x1B[2J\x1B[H"); 
@@ -182,4 +409,48 @@ fn main() -> isize {
     0
 }
 
+/// Names of `ulimit`, cpu in ticks, heap and stack in bytes, rss in pages
+const RLIMITS: [(&str, Rlimit); 5] = [
+    ("cpu", Rlimit::CpuTicks),
+    ("heap", Rlimit::Heap),
+    ("stack", Rlimit::Stack),
+    ("rss", Rlimit::ResidentPages),
+    ("nofile", Rlimit::OpenFiles),
+];
+
+/// Parse a limit given to `ulimit`, a number or `unlimited`
+fn parse_limit(value: &str) -> Option<usize> {
+    match value {
+        "unlimited" => Some(RLIM_INFINITY),
+        value => value.parse().ok(),
+    }
+}
+
+/// Show a limit the way `ulimit` takes it
+fn limit_str(value: usize) -> string::String {
+    match value {
+        RLIM_INFINITY => string::String::from("unlimited"),
+        value => format!("{}", value),
+    }
+}
+
+/// Run the app `name` with `args` in the foreground, without reporting
+/// its exit code
+fn run_tool<'a>(name: &'a str, args: impl Iterator<Item = &'a str>) -> Result<(), Errno> {
+    let args: vec::Vec<&str> = core::iter::once(name)
+        .chain(args.filter(|arg| !arg.is_empty()))
+        .collect();
+    let pid = sys_spawn(name, &args, &[])?;
+    wait_foreground(pid).map(|_| ())
+}
+
+/// Wait for `pid` in its own group owning the console, then take it back
+fn wait_foreground(pid: u16) -> Result<isize, Errno> {
+    sys_set_pgid(pid, pid).ok();
+    sys_set_foreground(pid).ok();
+    let ret = sys_wait_pid(pid);
+    sys_set_foreground(sys_get_pid()).ok();
+    ret
+}
+
 entry!(main);
\ No newline at end of file
@@ -8,7 +8,7 @@ extern crate lib;
 fn main() -> isize {
     
     println!("start to sleep");
-    sleep(3);
+    sleep(3000);
     println!("sleep end");
     233
 }
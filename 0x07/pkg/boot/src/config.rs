@@ -17,6 +17,10 @@ pub struct Config<'a> {
     pub cmdline: &'a str,
     /// Load apps into memory, when no fs implemented in kernel
     pub load_apps: bool,
+    /// Scheduling policy of the kernel: `rr`, `priority` or `mlfq`
+    pub scheduler: &'a str,
+    /// Number of CPUs the kernel brings up, the APIC ids are taken as 0..n
+    pub cpu_count: u64,
 }
 
 const DEFAULT_CONFIG: Config = Config {
@@ -27,6 +31,8 @@ const DEFAULT_CONFIG: Config = Config {
     kernel_path: "\\KERNEL.ELF",
     cmdline: "",
     load_apps: false,
+    scheduler: "rr",
+    cpu_count: 1,
 };
 
 impl<'a> Config<'a> {
@@ -65,6 +71,8 @@ impl<'a> Config<'a> {
             "kernel_stack_auto_grow" => self.kernel_stack_auto_grow = r10,
             "cmdline" => self.cmdline = value,
             "load_apps" => self.load_apps = r10 != 0,
+            "scheduler" => self.scheduler = value,
+            "cpu_count" => self.cpu_count = r10,
             _ => warn!("undefined config key: {}", key),
         }
     }
@@ -52,6 +52,13 @@ pub struct BootInfo {
     pub system_table: NonNull<core::ffi::c_void>,
     pub loaded_apps: Option<AppList>,
     pub kernel_pages: KernelPages,
+    /// The `scheduler` key of the boot config
+    pub scheduler: ArrayString<16>,
+    /// The `cpu_count` key of the boot config
+    pub cpu_count: usize,
+    /// Physical address of a page below 1MiB the application processors
+    /// start in, 0 if none was allocated
+    pub ap_trampoline: u64,
 }
 
 /// Get current page table from CR3
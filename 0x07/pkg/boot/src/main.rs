@@ -12,8 +12,9 @@ use alloc::boxed::Box;
 use alloc::vec;
 use config::Config;
 use elf::{load_elf, map_physical_memory, map_range};
-use uefi::boot::exit_boot_services;
+use uefi::boot::{exit_boot_services, AllocateType};
 use uefi::{Status, entry};
+use arrayvec::ArrayString;
 use x86_64::registers::control::*;
 
 use uefi::mem::memory_map::MemoryMap;
@@ -118,6 +119,24 @@ fn efi_main() -> Status {
     unsafe {
         Cr0::update(|cr0| cr0.insert(Cr0Flags::WRITE_PROTECT));
     }
+    // application processors start in real mode, their first page has to be
+    // below 1MiB, it is identity mapped like the rest of the low memory
+    let ap_trampoline = if config.cpu_count > 1 {
+        match uefi::boot::allocate_pages(
+            AllocateType::MaxAddress(0x9_F000),
+            MemoryType::LOADER_DATA,
+            1,
+        ) {
+            Ok(page) => page.as_ptr() as u64,
+            Err(e) => {
+                warn!("Failed to allocate AP trampoline: {:?}", e);
+                0
+            }
+        }
+    } else {
+        0
+    };
+
     let kpages = get_page_usage(&elf);
     free_elf(elf);
 
@@ -138,7 +157,10 @@ fn efi_main() -> Status {
         physical_memory_offset: config.physical_memory_offset,
         system_table,
         loaded_apps:apps,
-        kernel_pages: kpages
+        kernel_pages: kpages,
+        scheduler: ArrayString::from(config.scheduler).unwrap_or_default(),
+        cpu_count: config.cpu_count as usize,
+        ap_trampoline,
     };
 
     // align stack to 8 bytes
@@ -4,11 +4,12 @@ use chrono::DateTime;
 use storage::fat16::Fat16;
 use storage::mbr::*;
 use storage::*;
-use alloc::format;
-use crate::alloc::string::ToString;
-pub static ROOTFS: spin::Once<Mount> = spin::Once::new();
+use syscall_def::{Dirent, Errno, TIME_UNKNOWN};
+use alloc::vec::Vec;
+use crate::proc::ProcFs;
+pub static ROOTFS: spin::Once<MountTable> = spin::Once::new();
 
-pub fn get_rootfs() -> &'static Mount {
+pub fn get_rootfs() -> &'static MountTable {
     ROOTFS.get().unwrap()
 }
 
@@ -26,65 +27,60 @@ pub fn init() {
 
     info!("Mounting filesystem...");
 
-    ROOTFS.call_once(|| Mount::new(Box::new(Fat16::new(part)), "/".into()));
+    ROOTFS.call_once(|| {
+        let mut table = MountTable::new(Mount::new(Box::new(Fat16::new(part)), "/".into()));
+        table.mount(Mount::new(Box::new(ProcFs::new()), "/proc".into()));
+        table
+    });
 
     trace!("Root filesystem: {:#?}", ROOTFS.get().unwrap());
 
     info!("Initialized Filesystem.");
 }
 
-pub fn ls(root_path: &str) {
-    let iter = match get_rootfs().read_dir(root_path) {
-        Ok(iter) => iter,
-        Err(err) => {
-            warn!("{:?}", err);
-            return;
+/// Map a filesystem error to the errno reported to user space
+pub fn fs_errno(err: &FsError) -> Errno {
+    match err {
+        FsError::FileNotFound | FsError::InvalidPath(_) => Errno::NotFound,
+        FsError::NotADirectory => Errno::NotADirectory,
+        FsError::NotAFile => Errno::IsADirectory,
+        FsError::ReadOnly => Errno::PermissionDenied,
+        FsError::NotSupported => Errno::NotSupported,
+        FsError::InvalidOperation | FsError::InvalidOffset | FsError::FileNameError(_) => {
+            Errno::InvalidArgument
         }
-    };
+        FsError::DeviceError(DeviceError::Busy) => Errno::Busy,
+        _ => Errno::IoError,
+    }
+}
+
+/// Entries of the directory `path` from the `start`-th on, as `Dirent`
+/// records followed by their names, as many as fit in `max_len` bytes
+pub fn dirents(path: &str, start: usize, max_len: usize) -> core::result::Result<Vec<u8>, Errno> {
+    let iter = get_rootfs().read_dir(path).map_err(|err| fs_errno(&err))?;
+
+    let mut buf = Vec::new();
+    for meta in iter.skip(start) {
+        let name = meta.name.as_bytes();
+        let times = [meta.created, meta.modified, meta.accessed]
+            .map(|time| time.map_or(TIME_UNKNOWN, |t| t.timestamp()));
+        let dirent = Dirent::new(name.len() as u16, meta.is_dir(), meta.len as u64, times);
 
-    // FIXME: format and print the file metadata
-    //      - use `for meta in iter` to iterate over the entries
-    //      - use `crate::humanized_size_short` for file size
-    //      - add '/' to the end of directory names
-    //      - format the date as you like
-    //      - do not forget to print the table header
-    println!(
-        "{:<12} {:<12} {:<12} {:<20} {:<20} {:<20}",
-        "Name", "Type", "Size", "Created Time", "Last Modified", "Last Access"
-    );
-    for meta in iter {
-        let filetype = {
-            if meta.is_dir() {
-                "Directory"
-            } else {
-                "File"
+        let offset = buf.len();
+        if offset + dirent.rec_len as usize > max_len {
+            // not even one entry fits
+            if offset == 0 {
+                return Err(Errno::InvalidArgument);
             }
-        };
-        let mut name = meta.name;
-        if filetype == "Directory" {
-            name.push('/');
+            break;
         }
-        let (num, unit) = crate::humanized_size(meta.len as u64);
-        let size = format!("{:.2} {}", num, unit);
-        let created_time = meta
-            .created
-            .unwrap()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
-        let last_modified = meta
-            .modified
-            .unwrap()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
-        let last_access = meta
-            .accessed
-            .unwrap()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
-
-        println!(
-            "{:<12} {:<12} {:<12} {:<20} {:<20} {:<20}",
-            name, filetype, size, created_time, last_modified, last_access
-        );
+        let header = unsafe {
+            core::slice::from_raw_parts(&dirent as *const Dirent as *const u8, size_of::<Dirent>())
+        };
+        buf.extend_from_slice(header);
+        buf.extend_from_slice(name);
+        buf.resize(offset + dirent.rec_len as usize, 0);
     }
+
+    Ok(buf)
 }
@@ -9,7 +9,7 @@
 //! Reference: [OSDev Wiki](https://wiki.osdev.org/APIC)
 
 pub use ioapic::{IOAPIC_ADDR, IoApic};
-pub use xapic::{LAPIC_ADDR, XApic};
+pub use xapic::{pit_wait, LAPIC_ADDR, XApic};
 
 mod ioapic;
 mod xapic;
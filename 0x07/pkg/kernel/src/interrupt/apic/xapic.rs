@@ -1,15 +1,24 @@
 use super::LocalApic;
+use crate::interrupt::clock;
 use crate::interrupt::consts::*;
 use crate::memory::address;
 use bit_field::BitField;
 use core::fmt::{Debug, Error, Formatter};
 use core::ptr::{read_volatile, write_volatile};
 use x86::cpuid::CpuId;
+use x86_64::instructions::port::{Port, PortWriteOnly};
 use bitflags::bitflags;
 
 /// Default physical address of xAPIC
 pub const LAPIC_ADDR: u64 = 0xFEE00000;
 
+/// Initial count of the periodic timer, i.e. the length of a tick
+const TIMER_INIT_COUNT: u32 = 0x20000;
+
+/// Input clock of the PIT, the reference the APIC timer is measured against
+const PIT_FREQUENCY: u64 = 1_193_182;
+const CALIBRATE_MILLIS: u64 = 10;
+
 
 
 pub struct XApic {
@@ -33,6 +42,81 @@ impl XApic {
     }
 }
 
+/// Start PIT channel 2 counting down `micros`, at most about 54ms,
+/// `pit_expired` tells when it is over
+unsafe fn pit_start(micros: u64) {
+    let mut control = Port::<u8>::new(0x61);
+    let mut command = PortWriteOnly::<u8>::new(0x43);
+    let mut channel2 = PortWriteOnly::<u8>::new(0x42);
+    let count = (PIT_FREQUENCY * micros / 1_000_000).min(u16::MAX as u64) as u16;
+
+    unsafe {
+        // gate of channel 2 low, speaker off
+        let value = control.read() & !0b11;
+        control.write(value);
+        // channel 2, lobyte/hibyte, mode 0: output goes high at terminal count
+        command.write(0b1011_0000);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+        control.write(value | 1);
+    }
+}
+
+fn pit_expired() -> bool {
+    unsafe { Port::<u8>::new(0x61).read() & (1 << 5) != 0 }
+}
+
+/// Busy wait for `micros` microseconds, at most about 54ms
+pub fn pit_wait(micros: u64) {
+    unsafe { pit_start(micros) };
+    while !pit_expired() {
+        core::hint::spin_loop();
+    }
+}
+
+impl XApic {
+    /// Length of `TIMER_INIT_COUNT` timer counts in nanoseconds
+    ///
+    /// the timer counts down at bus frequency, which is not known, so it
+    /// runs one-shot while PIT channel 2 counts `CALIBRATE_MILLIS`
+    unsafe fn calibrate_timer(&mut self) -> u64 {
+        let elapsed = unsafe {
+            // masked and one-shot while measuring
+            self.write(0x320, 1 << 16);
+            self.write(0x380, u32::MAX);
+            pit_start(CALIBRATE_MILLIS * 1000);
+            while !pit_expired() {}
+            let elapsed = u32::MAX - self.read(0x390);
+            self.write(0x380, 0);
+            elapsed
+        };
+
+        TIMER_INIT_COUNT as u64 * CALIBRATE_MILLIS * 1_000_000 / (elapsed as u64).max(1)
+    }
+
+    /// Start the processor with `apic_id` at physical address `vector << 12`
+    /// in real mode, with the INIT-SIPI-SIPI sequence
+    pub fn start_ap(&mut self, apic_id: u32, vector: u8) {
+        const INIT: u64 = 5 << 8;
+        const STARTUP: u64 = 6 << 8;
+        const ASSERT: u64 = 1 << 14;
+        let dest = (apic_id as u64) << 56;
+
+        self.set_icr(dest | INIT | ASSERT);
+        pit_wait(10_000);
+        for _ in 0..2 {
+            self.set_icr(dest | STARTUP | ASSERT | vector as u64);
+            pit_wait(200);
+        }
+    }
+
+    /// Send the interrupt `vector` to the processor with `apic_id`
+    pub fn send_ipi(&mut self, apic_id: u32, vector: u8) {
+        const ASSERT: u64 = 1 << 14;
+        self.set_icr((apic_id as u64) << 56 | ASSERT | vector as u64);
+    }
+}
+
 impl LocalApic for XApic {
     /// If this type APIC is supported
     fn support() -> bool {
@@ -56,6 +140,10 @@ impl LocalApic for XApic {
             // FIXME: The timer repeatedly counts down at bus frequency
 
             self.write(0x3E0, 0b1011);
+            // every CPU runs at the same bus frequency, measure it once
+            if clock::tick_nanos() == 0 {
+                clock::set_tick_nanos(self.calibrate_timer());
+            }
 
             let mut lvt_timer = self.read(0x320);
             // clear and set Vector
@@ -65,7 +153,7 @@ impl LocalApic for XApic {
             lvt_timer |= 1 << 17; // set Timer Periodic Mode
             self.write(0x320, lvt_timer);
 
-            self.write(0x380, 0x20000); // set initial count to 0x20000
+            self.write(0x380, TIMER_INIT_COUNT); // set initial count
             // FIXME: Disable logical interrupt lines (LINT0, LINT1)
             self.write(0x350, 1 << 16); // set Mask LINT0
             self.write(0x360, 1 << 16);
@@ -2,6 +2,14 @@ use super::consts::*;
 use x86_64::structures::idt::{InterruptDescriptorTable,InterruptStackFrame};
 use crate::proc;
 use crate::memory::gdt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Timer interrupts of the bootstrap processor since its APIC was set up,
+/// the kernel's monotonic clock
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Length of a tick, measured when the local APIC timer is calibrated
+static TICK_NANOS: AtomicU64 = AtomicU64::new(0);
 
 pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     unsafe{idt[Interrupts::IrqBase as u8 + Irq::Timer as u8]
@@ -11,9 +19,37 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
 pub extern "C" fn clock(mut context: proc::ProcessContext){
     
     x86_64::instructions::interrupts::without_interrupts(|| {
+        super::stats::count(super::stats::Source::Timer);
+        // every CPU has a timer, the clock follows the bootstrap one
+        if proc::is_bsp() {
+            TICKS.fetch_add(1, Ordering::Relaxed);
+        }
         proc::switch(&mut context);
+        proc::handle_signals(&mut context);
         super::ack();
     });
 }
 
 as_handler!(clock);
+
+pub fn set_tick_nanos(nanos: u64) {
+    TICK_NANOS.store(nanos.max(1), Ordering::Relaxed);
+}
+
+pub fn tick_nanos() -> u64 {
+    TICK_NANOS.load(Ordering::Relaxed)
+}
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since the timer started, with the resolution of one tick
+pub fn now_nanos() -> u64 {
+    ticks() * tick_nanos()
+}
+
+/// Number of ticks covering `nanos`, rounded up
+pub fn nanos_to_ticks(nanos: u64) -> u64 {
+    nanos.div_ceil(tick_nanos().max(1))
+}
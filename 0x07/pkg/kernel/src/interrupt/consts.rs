@@ -44,5 +44,7 @@ pub enum Irq {
     Ide0 = 14,
     Ide1 = 15,
     Error = 19,
+    /// sent by another processor, see `tlb::shootdown`
+    TlbShootdown = 20,
     Spurious = 31,
 }
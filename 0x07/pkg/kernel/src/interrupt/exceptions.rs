@@ -1,4 +1,7 @@
 use crate::{memory::*, proc};
+use crate::proc::ProcessContext;
+use super::stats::{self, Source};
+use syscall_def::Signal;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 use x86_64::VirtAddr;
@@ -28,7 +31,7 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     idt.hv_injection_exception
         .set_handler_fn(hv_injection_exception_handler);
     idt.invalid_tss.set_handler_fn(invalid_tss_handler);
-    idt.invalid_opcode.set_handler_fn(invalid_opcode);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
     idt.machine_check.set_handler_fn(machine_check_handler);
     idt.non_maskable_interrupt
         .set_handler_fn(non_maskable_interrupt);
@@ -49,10 +52,18 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
         .set_handler_fn(vmm_commumication_exception_handler);
 }
 
-pub extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: DIVIDE ERROR\n\n{:#?}", stack_frame);
+// faults raised in user mode terminate the process, in the kernel they are fatal
+
+pub extern "C" fn divide_error(mut context: ProcessContext) {
+    stats::count(Source::Fault);
+    if !context.is_user() {
+        panic!("EXCEPTION: DIVIDE ERROR\n\n{:#?}", context);
+    }
+    proc::fault(Signal::FloatingPoint, &mut context);
 }
 
+as_handler!(divide_error);
+
 pub extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
@@ -66,35 +77,37 @@ pub extern "x86-interrupt" fn double_fault_handler(
     );
 }
 
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: PageFaultErrorCode,
-) {
-    if !crate::proc::handle_page_fault(
-        Cr2::read().unwrap_or(VirtAddr::new_truncate(0xdeadbeef)),
-        err_code,
-    ) {
-        warn!(
+pub extern "C" fn page_fault(mut context: ProcessContext, err_code: u64) {
+    stats::count(Source::PageFault);
+    let err_code = PageFaultErrorCode::from_bits_truncate(err_code);
+    let addr = Cr2::read().unwrap_or(VirtAddr::new_truncate(0xdeadbeef));
+    if proc::handle_page_fault(addr, err_code) {
+        return;
+    }
+    if !context.is_user() {
+        panic!(
             "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}\n{:#?}",
-            err_code,
-            Cr2::read().unwrap_or(VirtAddr::new_truncate(0xdeadbeef)),
-            stack_frame
+            err_code, addr, context
         );
-        // FIXME: print info about which process causes page fault?
-
-        info!("Page fault occurred for process: {:?}", proc::manager::get_process_manager().current().pid());
     }
+    warn!("Page fault at {:#x}, ERROR_CODE: {:?}", addr, err_code);
+    proc::fault(Signal::Segfault, &mut context);
 }
-pub extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: u64,
-) {
-   
-    panic!(
-        "EXCEPTION: GENERAL PROTECTION FAULT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
-        err_code, stack_frame
-    );
+
+as_handler_with_err!(page_fault, PageFaultErrorCode);
+
+pub extern "C" fn general_protection_fault(mut context: ProcessContext, err_code: u64) {
+    stats::count(Source::Fault);
+    if !context.is_user() {
+        panic!(
+            "EXCEPTION: GENERAL PROTECTION FAULT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
+            err_code, context
+        );
+    }
+    proc::fault(Signal::Segfault, &mut context);
 }
+
+as_handler_with_err!(general_protection_fault, u64);
 pub extern "x86-interrupt" fn alignment_check_handler(
     stack_frame: InterruptStackFrame,
     err_code: u64,
@@ -129,9 +142,15 @@ pub extern "x86-interrupt" fn stack_segment_fault(stack_frame: InterruptStackFra
     );
 }
 
-pub extern "x86-interrupt" fn invalid_opcode(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: INVALID OPCODE\n\n{:#?}", stack_frame);
+pub extern "C" fn invalid_opcode(mut context: ProcessContext) {
+    stats::count(Source::Fault);
+    if !context.is_user() {
+        panic!("EXCEPTION: INVALID OPCODE\n\n{:#?}", context);
+    }
+    proc::fault(Signal::Illegal, &mut context);
 }
+
+as_handler!(invalid_opcode);
 pub extern "x86-interrupt" fn segment_not_present(stack_frame: InterruptStackFrame, err_code: u64) {
     panic!(
         "EXCEPTION: SEGMENT NOT PRESENT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
@@ -3,6 +3,9 @@ pub mod clock;
 mod consts;
 mod exceptions;
 mod serial;
+pub mod smp;
+pub mod stats;
+pub mod tlb;
 use crate::memory::address;
 use crate::memory::physical_to_virtual;
 use apic::*;
@@ -17,6 +20,7 @@ lazy_static! {
             exceptions::register_idt(&mut idt);
             clock::register_idt(&mut idt);
             serial::register_idt(&mut idt);
+            tlb::register_idt(&mut idt);
             syscall::register_idt(&mut idt);
         }
         idt
@@ -29,8 +33,8 @@ pub fn init() {
     // FIXME: check and init APIC
     if let Some(_) = address::PHYSICAL_OFFSET.get() {
         if XApic::support() {
-            let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
-            lapic.cpu_init();
+            init_lapic();
+            info!("Timer tick: {} ns", clock::tick_nanos());
         }
     } else {
         // 如果PHYSICAL_OFFSET未初始化，记录警告信息
@@ -41,9 +45,18 @@ pub fn init() {
     // FIXME: enable serial irq with IO APIC (use enable_irq)
     enable_irq(consts::Irq::Serial0 as u8, 0);
 
+    syscall::init();
+
     info!("Interrupts Initialized.");
 }
 
+fn init_lapic() {
+    let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
+    lapic.cpu_init();
+    // mapped at the same address on every processor
+    crate::proc::init_apic_id(physical_to_virtual(LAPIC_ADDR));
+}
+
 #[inline(always)]
 pub fn enable_irq(irq: u8, cpuid: u8) {
     let mut ioapic = unsafe { IoApic::new(physical_to_virtual(IOAPIC_ADDR)) };
@@ -4,6 +4,7 @@ use crate::drivers::serial;
 use crate::drivers::input::push_key;
 use crate::drivers::input;
 use crate::drivers::serial::get_serial_for_sure;
+use syscall_def::Signal;
 
 pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     idt[Interrupts::IrqBase as u8 + Irq::Serial0 as u8]
@@ -11,6 +12,7 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
 }
 
 pub extern "x86-interrupt" fn serial_handler(_st: InterruptStackFrame) {
+    super::stats::count(super::stats::Source::Serial);
     receive();
     super::ack();
 }
@@ -23,6 +25,10 @@ fn receive() {
     drop(serial);
 
     if let Some(data) = data {
+        // Ctrl-C interrupts the foreground group instead of being read
+        if data == 0x03 && crate::proc::signal_foreground(Signal::Interrupt) {
+            return;
+        }
         input::push_key(data);
     }
     // FIXME: receive character from uart 16550, put it into INPUT_BUFFER
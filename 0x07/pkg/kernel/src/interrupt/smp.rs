@@ -0,0 +1,192 @@
+//! Bring-up of the application processors
+//!
+//! The bootstrap processor copies a trampoline to the page below 1MiB the
+//! bootloader reserved and starts the other processors there, one at a time,
+//! with INIT-SIPI-SIPI. The trampoline switches from real mode straight to
+//! long mode on the kernel page table, which identity maps the low memory,
+//! and calls `ap_entry` on a stack of its own. APIC ids are taken as the
+//! indices `0..cpu_count`, as QEMU numbers them.
+//!
+//! Mappings a process loses while other threads of it run on other CPUs
+//! are shot down on those CPUs with an IPI, see `tlb`.
+
+use super::apic::*;
+use crate::memory::{gdt, physical_to_virtual};
+use crate::proc::{self, MAX_CPU_COUNT};
+use core::mem::offset_of;
+use core::sync::atomic::{fence, Ordering};
+use x86_64::registers::control::Cr3;
+
+/// Where `TrampolineData` is in the trampoline page, after the code
+const DATA_OFFSET: usize = 0x800;
+
+const AP_STACK_SIZE: usize = 0x10000;
+
+/// How long to wait for a started processor to come online
+const ONLINE_TIMEOUT_MILLIS: usize = 100;
+
+/// Filled by the bootstrap processor before starting each processor
+#[repr(C)]
+struct TrampolineData {
+    /// null, 64-bit code (0x08) and data (0x10) segments
+    gdt: [u64; 3],
+    _pad: [u16; 3],
+    /// pseudo descriptor for `lgdt`
+    gdt_limit: u16,
+    gdt_base: u64,
+    /// far pointer to `ap_trampoline_long`
+    jump_offset: u32,
+    jump_selector: u16,
+    _pad2: u16,
+    cr3: u64,
+    stack_top: u64,
+    entry: u64,
+    cpu: u64,
+}
+
+core::arch::global_asm!(
+    ".pushsection .text",
+    ".code16",
+    ".global ap_trampoline_start",
+    "ap_trampoline_start:",
+    "    cli",
+    "    cld",
+    "    mov %cs, %ax",
+    "    mov %ax, %ds",
+    // PAE
+    "    mov %cr4, %eax",
+    "    or $(1 << 5), %eax",
+    "    mov %eax, %cr4",
+    "    movl {cr3}, %eax",
+    "    mov %eax, %cr3",
+    // EFER: long mode and no-execute, the kernel maps pages NX
+    "    mov $0xc0000080, %ecx",
+    "    rdmsr",
+    "    or $((1 << 8) | (1 << 11)), %eax",
+    "    wrmsr",
+    "    lgdtl {gdtr}",
+    // paging and protection at once
+    "    mov %cr0, %eax",
+    "    or $((1 << 31) | 1), %eax",
+    "    mov %eax, %cr0",
+    "    ljmpl *{jump}",
+    ".code64",
+    ".global ap_trampoline_long",
+    "ap_trampoline_long:",
+    "    mov $0x10, %ax",
+    "    mov %ax, %ds",
+    "    mov %ax, %es",
+    "    mov %ax, %ss",
+    "    lea ap_trampoline_start(%rip), %rbx",
+    "    mov {stack_top}(%rbx), %rsp",
+    "    mov {cpu}(%rbx), %rdi",
+    "    call *{entry}(%rbx)",
+    "    ud2",
+    ".global ap_trampoline_end",
+    "ap_trampoline_end:",
+    ".popsection",
+    cr3 = const DATA_OFFSET + offset_of!(TrampolineData, cr3),
+    gdtr = const DATA_OFFSET + offset_of!(TrampolineData, gdt_limit),
+    jump = const DATA_OFFSET + offset_of!(TrampolineData, jump_offset),
+    stack_top = const DATA_OFFSET + offset_of!(TrampolineData, stack_top),
+    cpu = const DATA_OFFSET + offset_of!(TrampolineData, cpu),
+    entry = const DATA_OFFSET + offset_of!(TrampolineData, entry),
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_long: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Start the application processors, `cpu_count` of the boot config
+pub fn init(boot_info: &boot::BootInfo) {
+    let count = boot_info.cpu_count.min(MAX_CPU_COUNT);
+    if count <= 1 {
+        return;
+    }
+    let page = boot_info.ap_trampoline;
+    if page == 0 {
+        warn!("No AP trampoline, running on one CPU.");
+        return;
+    }
+    let cr3 = Cr3::read().0.start_address().as_u64();
+    if cr3 >> 32 != 0 {
+        warn!("Kernel page table above 4GiB, running on one CPU.");
+        return;
+    }
+
+    let (start, long, end) = unsafe {
+        (
+            &ap_trampoline_start as *const u8,
+            &ap_trampoline_long as *const u8,
+            &ap_trampoline_end as *const u8,
+        )
+    };
+    let code_size = end as usize - start as usize;
+    assert!(code_size <= DATA_OFFSET, "AP trampoline too large");
+
+    let base = physical_to_virtual(page);
+    let data = unsafe {
+        core::ptr::copy_nonoverlapping(start, base as *mut u8, code_size);
+        &mut *((base + DATA_OFFSET as u64) as *mut TrampolineData)
+    };
+    *data = TrampolineData {
+        gdt: [0, 0x00af_9a00_0000_ffff, 0x00cf_9200_0000_ffff],
+        _pad: [0; 3],
+        gdt_limit: (size_of::<[u64; 3]>() - 1) as u16,
+        gdt_base: page + (DATA_OFFSET + offset_of!(TrampolineData, gdt)) as u64,
+        jump_offset: (page + (long as usize - start as usize) as u64) as u32,
+        jump_selector: 0x08,
+        _pad2: 0,
+        cr3,
+        stack_top: 0,
+        entry: ap_entry as usize as u64,
+        cpu: 0,
+    };
+
+    let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
+    let bsp = lapic.id() as usize;
+    for cpu in (0..count).filter(|&cpu| cpu != bsp) {
+        data.stack_top = gdt::alloc_stack(AP_STACK_SIZE).as_u64();
+        data.cpu = cpu as u64;
+        fence(Ordering::SeqCst);
+
+        lapic.start_ap(cpu as u32, (page >> 12) as u8);
+        // the trampoline data is reused by the next one
+        if !wait_online(cpu) {
+            warn!("CPU {} did not come up, not starting the others.", cpu);
+            break;
+        }
+    }
+
+    let online = (0..MAX_CPU_COUNT).filter(|&cpu| proc::is_online(cpu)).count();
+    info!("SMP Initialized, {} CPUs online.", online);
+}
+
+fn wait_online(cpu: usize) -> bool {
+    for _ in 0..ONLINE_TIMEOUT_MILLIS {
+        if proc::is_online(cpu) {
+            return true;
+        }
+        pit_wait(1000);
+    }
+    proc::is_online(cpu)
+}
+
+/// First Rust code of an application processor, on the stack the bootstrap
+/// processor allocated, interrupts still disabled
+extern "C" fn ap_entry(cpu: usize) -> ! {
+    gdt::init_ap(cpu);
+    super::IDT.load();
+    super::init_lapic();
+    super::syscall::init();
+    proc::init_ap();
+    info!("CPU {} online.", cpu);
+
+    x86_64::instructions::interrupts::enable();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
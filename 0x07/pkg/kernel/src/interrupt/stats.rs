@@ -0,0 +1,55 @@
+//! Interrupt counters, per source and per processor, for `/proc/interrupts`
+
+use crate::proc::{cpu_id, MAX_CPU_COUNT};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Source {
+    Timer,
+    Serial,
+    Syscall,
+    PageFault,
+    /// faults other than page faults, user ones end the process
+    Fault,
+    TlbShootdown,
+}
+
+impl Source {
+    pub const ALL: [Source; 6] = [
+        Source::Timer,
+        Source::Serial,
+        Source::Syscall,
+        Source::PageFault,
+        Source::Fault,
+        Source::TlbShootdown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Timer => "timer",
+            Source::Serial => "serial",
+            Source::Syscall => "syscall",
+            Source::PageFault => "page fault",
+            Source::Fault => "fault",
+            Source::TlbShootdown => "tlb",
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZEROS: [AtomicU64; MAX_CPU_COUNT] = [ZERO; MAX_CPU_COUNT];
+
+static COUNTS: [[AtomicU64; MAX_CPU_COUNT]; Source::ALL.len()] = [ZEROS; Source::ALL.len()];
+
+/// Count an interrupt from `source` on the current processor
+#[inline]
+pub fn count(source: Source) {
+    COUNTS[source as usize][cpu_id()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Interrupts from `source` handled by processor `cpu`
+pub fn get(source: Source, cpu: usize) -> u64 {
+    COUNTS[source as usize][cpu].load(Ordering::Relaxed)
+}
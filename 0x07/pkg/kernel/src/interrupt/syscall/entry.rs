@@ -0,0 +1,169 @@
+//! Fast system call entry through the `syscall` instruction
+//!
+//! The entry builds the same frame as the `int 0x80` handler (an interrupt
+//! stack frame followed by the registers), so `syscall` and the dispatcher
+//! work on a regular `ProcessContext`. On the way out, `sysretq` is used when
+//! the context still looks like the one `syscall` left, otherwise the frame
+//! is consumed by `iretq`, e.g. after switching to another process.
+
+use crate::memory::gdt;
+use crate::proc::{cpu_id, ProcessContext, MAX_CPU_COUNT};
+use crate::utils::regs::RegistersValue;
+use core::mem::offset_of;
+use core::ptr::addr_of_mut;
+use x86_64::registers::model_specific::{Efer, EferFlags, KernelGsBase, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::InterruptStackFrameValue;
+use x86_64::VirtAddr;
+
+/// Scratch data reached through `gs` after `swapgs` on `syscall` entry
+#[repr(C)]
+struct SyscallScratch {
+    kernel_rsp: u64,
+    user_rsp: u64,
+    user_cs: u64,
+    user_ss: u64,
+}
+
+/// One per CPU, `KernelGsBase` of each CPU points to its own
+static mut SCRATCH: [SyscallScratch; MAX_CPU_COUNT] = [const {
+    SyscallScratch {
+        kernel_rsp: 0,
+        user_rsp: 0,
+        user_cs: 0,
+        user_ss: 0,
+    }
+}; MAX_CPU_COUNT];
+
+/// Size of the pushed general purpose registers, the stack frame follows them
+const REGS_SIZE: usize = 15 * 8;
+
+/// Set up the `syscall` instruction on the current CPU
+pub fn init() {
+    let selector = gdt::get_selector();
+    let user_selector = gdt::get_user_selector();
+
+    unsafe {
+        let scratch = &mut (*addr_of_mut!(SCRATCH))[cpu_id()];
+        // keep the stack 16-byte aligned at `call`, like the CPU does for interrupts
+        scratch.kernel_rsp = gdt::syscall_stack_top().align_down(16u64).as_u64();
+        scratch.user_cs = user_selector.user_code_selector.0 as u64;
+        scratch.user_ss = user_selector.user_data_selector.0 as u64;
+
+        KernelGsBase::write(VirtAddr::from_ptr(scratch as *mut SyscallScratch));
+        Star::write(
+            user_selector.user_code_selector,
+            user_selector.user_data_selector,
+            selector.code_selector,
+            selector.data_selector,
+        )
+        .expect("Invalid GDT layout for syscall");
+        LStar::write(VirtAddr::new(syscall_entry as usize as u64));
+        SFMask::write(
+            RFlags::INTERRUPT_FLAG
+                | RFlags::TRAP_FLAG
+                | RFlags::DIRECTION_FLAG
+                | RFlags::ALIGNMENT_CHECK,
+        );
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+
+    info!("Syscall Fast Path Initialized.");
+}
+
+#[naked]
+unsafe extern "C" fn syscall_entry() {
+    unsafe {
+        core::arch::naked_asm!("
+        swapgs
+        mov gs:[{user_rsp}], rsp
+        mov rsp, gs:[{kernel_rsp}]
+
+        push qword ptr gs:[{user_ss}]
+        push qword ptr gs:[{user_rsp}]
+        push r11
+        push qword ptr gs:[{user_cs}]
+        push rcx
+
+        push rbp
+        push rax
+        push rbx
+        push rcx
+        push rdx
+        push rsi
+        push rdi
+        push r8
+        push r9
+        push r10
+        push r11
+        push r12
+        push r13
+        push r14
+        push r15
+        call {handler}
+
+        mov rax, [rsp + {rip}]
+        cmp rax, [rsp + {rcx}]
+        jne 2f
+        shr rax, 47
+        jnz 2f
+        mov rax, [rsp + {rflags}]
+        cmp rax, [rsp + {r11}]
+        jne 2f
+        mov ax, [rsp + {cs}]
+        cmp ax, gs:[{user_cs}]
+        jne 2f
+
+        pop r15
+        pop r14
+        pop r13
+        pop r12
+        pop r11
+        pop r10
+        pop r9
+        pop r8
+        pop rdi
+        pop rsi
+        pop rdx
+        pop rcx
+        pop rbx
+        pop rax
+        pop rbp
+        mov rsp, [rsp + {user_rsp_in_frame}]
+        swapgs
+        sysretq
+
+    2:
+        pop r15
+        pop r14
+        pop r13
+        pop r12
+        pop r11
+        pop r10
+        pop r9
+        pop r8
+        pop rdi
+        pop rsi
+        pop rdx
+        pop rcx
+        pop rbx
+        pop rax
+        pop rbp
+        swapgs
+        iretq",
+        kernel_rsp = const offset_of!(SyscallScratch, kernel_rsp),
+        user_rsp = const offset_of!(SyscallScratch, user_rsp),
+        user_cs = const offset_of!(SyscallScratch, user_cs),
+        user_ss = const offset_of!(SyscallScratch, user_ss),
+        rcx = const offset_of!(RegistersValue, rcx),
+        r11 = const offset_of!(RegistersValue, r11),
+        rip = const REGS_SIZE + offset_of!(InterruptStackFrameValue, instruction_pointer),
+        cs = const REGS_SIZE + offset_of!(InterruptStackFrameValue, code_segment),
+        rflags = const REGS_SIZE + offset_of!(InterruptStackFrameValue, cpu_flags),
+        user_rsp_in_frame = const offset_of!(InterruptStackFrameValue, stack_pointer),
+        handler = sym super::syscall,
+        );
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<ProcessContext>() == REGS_SIZE + 5 * 8);
@@ -3,9 +3,11 @@ use alloc::format;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 use x86_64::PrivilegeLevel::Ring3;
 // NOTE: import `ysos_syscall` package as `syscall_def` in Cargo.toml
-use syscall_def::Syscall;
+use syscall_def::{Errno, Syscall, TraceMode};
 
+mod entry;
 mod service;
+mod trace;
 use super::consts;
 
 // FIXME: write syscall service handler in `service.rs`
@@ -28,76 +30,184 @@ pub extern "C" fn syscall(mut context: ProcessContext) {
 
 as_handler!(syscall);
 
+/// Enable the `syscall` instruction, see `entry.rs`
+pub fn init() {
+    entry::init();
+}
+
 #[derive(Clone, Debug)]
 pub struct SyscallArgs {
     pub syscall: Syscall,
     pub arg0: usize,
     pub arg1: usize,
     pub arg2: usize,
+    pub arg3: usize,
+    pub arg4: usize,
+    pub arg5: usize,
 }
 
 pub fn dispatcher(context: &mut ProcessContext) {
+    // arguments follow the Linux convention: rdi, rsi, rdx, r10, r8, r9
+    // (r10 rather than rcx, which the `syscall` instruction overwrites)
     let args = super::syscall::SyscallArgs::new(
         Syscall::from(context.regs.rax),
         context.regs.rdi,
         context.regs.rsi,
         context.regs.rdx,
+        context.regs.r10,
+        context.regs.r8,
+        context.regs.r9,
     );
 
-    // NOTE: you may want to trace syscall arguments
-    // trace!("{}", args);
+    let pid = get_pid();
+    count_syscall();
+    super::stats::count(super::stats::Source::Syscall);
+    let trace_mode = current_trace_mode();
+    if trace_mode != TraceMode::Off {
+        trace::syscall_entry(trace_mode, pid, &args);
+    }
 
     match args.syscall {
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
-        Syscall::Read => /* FIXME: read from fd & return length */ context.set_rax(sys_read(&args)),
+        Syscall::Read => /* FIXME: read from fd & return length */ context.set_result(sys_read(&args)),
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
-        Syscall::Write =>  /* FIXME: write to fd & return length */context.set_rax(sys_write(&args)),
+        Syscall::Write =>  /* FIXME: write to fd & return length */context.set_result(sys_write(&args)),
 
         // None -> pid: u16
         Syscall::GetPid => context.set_rax(sys_get_pid()),
         Syscall::Fork => sys_fork(context),
-        // path: &str (ptr: arg0 as *const u8, len: arg1) -> pid: u16
-        Syscall::Spawn =>  /* FIXME: spawn process from name */ context.set_rax(spawn_process(&args)),
+        // path: &str (arg0, arg1), argv (arg2, arg3), envp (arg4, arg5) -> pid: u16
+        Syscall::Spawn =>  /* FIXME: spawn process from name */ context.set_result(spawn_process(&args)),
+        // path: &str (arg0, arg1), argv (arg2, arg3), envp (arg4, arg5),
+        // does not return on success
+        Syscall::Exec => sys_exec(&args, context),
         // ret: arg0 as isize
         Syscall::Exit => exit_process(&args,context),
-        // pid: arg0 as u16 -> status: isize
+        // entry: arg0 as fn(usize), arg: arg1, stack_top: arg2 -> tid: u16
+        Syscall::ThreadCreate => context.set_result(sys_thread_create(&args)),
+        // tid: arg0 as u16, status: arg1 as *mut isize (nullable)
+        Syscall::ThreadJoin => sys_thread_join(&args, context),
+        // ret: arg0 as isize, the main thread exits the whole process
+        Syscall::ThreadExit => sys_thread_exit(&args, context),
+        // pid: arg0 as isize (-1 for any child), status: arg1 as *mut isize
+        // (nullable), flags: arg2 (WNOHANG) -> pid: u16 (0 if none exited)
         Syscall::WaitPid =>  /* FIXME: check if the process is running or get retcode */sys_wait_pid(&args,context),
 
+        // nanos: arg0 as u64, blocks for at least that long
+        Syscall::Sleep => sys_sleep(&args, context),
         // None
-        Syscall::Stat =>  /* FIXME: list processes */ list_process() ,
+        Syscall::Yield => sys_yield(context),
+        // None
+        Syscall::Stat =>  /* FIXME: list processes */ {
+            list_process();
+            context.set_result(Ok(0));
+        }
+        // buf: &mut [ProcInfo] (ptr: arg0, len: arg1) -> count of all processes
+        Syscall::ListProc => context.set_result(sys_list_proc(&args)),
         // None
-        Syscall::ListApp => /* FIXME: list available apps */ sys_list_app(),
+        Syscall::ListApp => /* FIXME: list available apps */ {
+            sys_list_app();
+            context.set_result(Ok(0));
+        }
 
         // ----------------------------------------------------
         // NOTE: following syscall examples are implemented
         // ----------------------------------------------------
 
-        // layout: arg0 as *const Layout -> ptr: *mut u8
-        Syscall::Allocate => context.set_rax(sys_allocate(&args)),
-        // ptr: arg0 as *mut u8
-        Syscall::Deallocate => sys_deallocate(&args),
+        // size: arg0, align: arg1 -> ptr: *mut u8
+        Syscall::Allocate => context.set_result(sys_allocate(&args)),
+        // ptr: arg0 as *mut u8, size: arg1, align: arg2
+        Syscall::Deallocate => context.set_result(sys_deallocate(&args)),
         Syscall::Time => context.set_rax(sys_time() as usize),
         // op: u8, key: u32, val: usize -> ret: any
         Syscall::Sem => sys_sem(&args, context),
         // Unknown        S
-        Syscall::ListDir => list_dir(&args),
-        Syscall::OpenFile => context.set_rax(sys_open_file(&args)),
-        Syscall::CloseFile => context.set_rax(sys_close_file(&args) as usize),
-        Syscall::Unknown => warn!("Unhandled syscall: {:x?}", context.regs.rax),
-        Syscall::Brk => context.set_rax(sys_brk(&args)),
+        // path: &str (ptr: arg0 as *const u8, len: arg1),
+        // buf: &mut [u8] (ptr: arg2, len: arg3), start: arg4 -> length
+        Syscall::GetDents => context.set_result(sys_get_dents(&args)),
+        // path: &str (ptr: arg0 as *const u8, len: arg1) -> fd: u8
+        Syscall::OpenFile => context.set_result(sys_open_file(&args)),
+        // fd: arg0 as u8
+        Syscall::CloseFile => context.set_result(sys_close_file(&args)),
+        Syscall::Unknown => {
+            warn!("Unhandled syscall: {:x?}", context.regs.rax);
+            context.set_result(Err(Errno::NotSupported));
+        }
+        // addr: arg0 (0 to query) -> heap end
+        Syscall::Brk => context.set_result(sys_brk(&args)),
+        // op: arg0 = 0, pid: arg1 (0 for self), mode: arg2
+        // op: arg0 = 1, buf: &mut [u8] (ptr: arg1, len: arg2) -> length
+        Syscall::Trace => context.set_result(sys_trace(&args)),
+        // pid: arg0 as u16 (0 for self), pgid: arg1 as u16 (0 for pid)
+        Syscall::SetPgid => context.set_result(sys_set_pgid(&args)),
+        // pid: arg0 as u16 (0 for self) -> pgid: u16
+        Syscall::GetPgid => context.set_result(sys_get_pgid(&args)),
+        // None -> sid: u16
+        Syscall::SetSid => context.set_result(sys_set_sid()),
+        // pid: arg0 as u16 (0 for self) -> sid: u16
+        Syscall::GetSid => context.set_result(sys_get_sid(&args)),
+        // pid: arg0 as u16 (0 for self), nice: arg1 as isize (clamped to -20..=19)
+        Syscall::SetPriority => context.set_result(sys_set_priority(&args)),
+        // pid: arg0 as u16 (0 for self) -> 20 - nice, like Linux
+        Syscall::GetPriority => context.set_result(sys_get_priority(&args)),
+        // resource: arg0 as Rlimit, value: arg1 as *mut RlimitValue
+        Syscall::GetRlimit => context.set_result(sys_get_rlimit(&args)),
+        // who: arg0 as RusageWho, usage: arg1 as *mut Rusage
+        Syscall::GetRusage => context.set_result(sys_get_rusage(&args)),
+        // resource: arg0 as Rlimit, value: arg1 as *const RlimitValue
+        Syscall::SetRlimit => context.set_result(sys_set_rlimit(&args)),
+        // pgid: arg0 as u16, gives the console to the group
+        Syscall::SetForeground => context.set_result(sys_set_foreground(&args)),
+        // None -> pgid: u16 (0 if the console has no owner)
+        Syscall::GetForeground => context.set_result(sys_get_foreground()),
+        // pid: arg0 as isize (0 own group, negative for a group), sig: arg1 (0 only checks)
+        Syscall::Kill => context.set_result(sys_kill(&args)),
+        // sig: arg0, handler: arg1 (SIG_DFL, SIG_IGN or address), restorer: arg2 -> old handler
+        Syscall::SigAction => context.set_result(sys_sigaction(&args)),
+        // how: arg0, set: arg1 -> old mask
+        Syscall::SigProcMask => context.set_result(sys_sigprocmask(&args)),
+        // None, restores the context saved when the handler was entered
+        Syscall::SigReturn => sigreturn(context),
+        // key: &str (arg0, arg1), buf: &mut [u8] (arg2, arg3) -> value length
+        Syscall::GetEnv => context.set_result(sys_get_env(&args)),
+        // key: &str (arg0, arg1), value: &str (arg2, arg3)
+        Syscall::SetEnv => context.set_result(sys_set_env(&args)),
+        // key: &str (arg0, arg1)
+        Syscall::UnsetEnv => context.set_result(sys_unset_env(&args)),
+        // buf: &mut [u8] (arg0, arg1) -> length of the NUL-terminated `KEY=VALUE` list
+        Syscall::ListEnv => context.set_result(sys_list_env(&args)),
         
         // path: &str (arg0 as *const u8, arg1 as len)
 
     }
+
+    // blocked, exited or switched away processes have no return value yet
+    if trace_mode != TraceMode::Off && !matches!(args.syscall, Syscall::Exit | Syscall::ThreadExit | Syscall::SigReturn) {
+        let ret = (get_pid() == pid).then(|| context.regs.rax);
+        trace::syscall_return(trace_mode, pid, &args.syscall, ret);
+    }
+
+    handle_signals(context);
 }
 
 impl SyscallArgs {
-    pub fn new(syscall: Syscall, arg0: usize, arg1: usize, arg2: usize) -> Self {
+    pub fn new(
+        syscall: Syscall,
+        arg0: usize,
+        arg1: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> Self {
         Self {
             syscall,
             arg0,
             arg1,
             arg2,
+            arg3,
+            arg4,
+            arg5,
         }
     }
 }
@@ -106,11 +216,14 @@ impl core::fmt::Display for SyscallArgs {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
-            "SYSCALL: {:<10} (0x{:016x}, 0x{:016x}, 0x{:016x})",
+            "SYSCALL: {:<10} (0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:016x})",
             format!("{:?}", self.syscall),
             self.arg0,
             self.arg1,
-            self.arg2
+            self.arg2,
+            self.arg3,
+            self.arg4,
+            self.arg5
         )
     }
 }
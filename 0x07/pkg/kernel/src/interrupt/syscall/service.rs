@@ -3,56 +3,136 @@ use crate::proc::*;
 use crate::proc;
 use crate::utils::*;
 use crate::filesystem;
+use crate::interrupt::clock;
 use x86_64::VirtAddr;
 use super::SyscallArgs;
+use crate::memory::user::{USER_HEAP_SIZE, USER_HEAP_START};
+use crate::proc::vm::{ARG_MAX, USER_SPACE_END};
+use alloc::{string::String, vec, vec::Vec};
+use syscall_def::signal::{SigMaskHow, SIG_DFL, SIG_IGN};
+use syscall_def::{Errno, ProcInfo, Rlimit, RlimitValue, Rusage, RusageWho, Signal, TraceMode, WNOHANG};
 
-pub fn spawn_process(args: &SyscallArgs) -> usize {
-    // FIXME: get app name by args
-    //       - core::str::from_utf8_unchecked
-    //       - core::slice::from_raw_parts
-    // FIXME: spawn the process by name
-    // FIXME: handle spawn error, return 0 if failed
-    // FIXME: return pid as usize
-    let path = unsafe{
-        core::str::from_utf8_unchecked(
-            core::slice::from_raw_parts(
-                args.arg0 as *const u8,args.arg1
-            )
-        )
-    };
-    match proc::spawn(path){
-        Some(pid) => pid.0 as usize,
-        None => 0
+/// A pid argument, rejected rather than truncated if it does not fit in
+/// a `ProcessId`
+fn pid_arg(arg: usize) -> Result<ProcessId, Errno> {
+    u16::try_from(arg).map(ProcessId).map_err(|_| Errno::InvalidArgument)
+}
+
+/// Copy the path (arg0, arg1), argv (arg2, arg3) and envp (arg4, arg5) of
+/// spawn and exec, argv and envp are NUL-terminated strings, e.g. `"a\0b\0"`
+fn program_args(args: &SyscallArgs) -> Result<[String; 3], Errno> {
+    if args.arg3 > ARG_MAX || args.arg5 > ARG_MAX {
+        return Err(Errno::ArgumentListTooLong);
     }
+    Ok([
+        user_str(args.arg0, args.arg1)?,
+        user_str(args.arg2, args.arg3)?,
+        user_str(args.arg4, args.arg5)?,
+    ])
 }
 
-pub fn sys_write(args: &SyscallArgs) -> usize {
-    // FIXME: get buffer and fd by args
-    //       - core::slice::from_raw_parts
-    // FIXME: call proc::write -> isize
-    // FIXME: return the result as usize
-    // let buf =unsafe{core::slice::from_raw_parts(args.arg1 as *const u8, args.arg2)};
-    // proc::write(args.arg0,buf)
-    let buf = unsafe{core::slice::from_raw_parts(args.arg1 as *const u8, args.arg2)};
-    proc::write(args.arg0 as u8, buf) as usize
-    
+pub fn spawn_process(args: &SyscallArgs) -> Result<usize, Errno> {
+    let [path, argv, envp] = program_args(args)?;
+    let argv: Vec<&str> = argv.split_terminator('\0').collect();
+    let envp: Vec<&str> = envp.split_terminator('\0').collect();
+    proc::spawn(&path, &argv, &envp).map(|pid| pid.0 as usize)
+}
+
+pub fn sys_exec(args: &SyscallArgs, context: &mut ProcessContext) {
+    // on success the context already points to the new image
+    let ret = program_args(args).and_then(|[path, argv, envp]| {
+        let argv: Vec<&str> = argv.split_terminator('\0').collect();
+        let envp: Vec<&str> = envp.split_terminator('\0').collect();
+        proc::exec(&path, &argv, &envp, context)
+    });
+    if let Err(err) = ret {
+        context.set_result(Err(err));
+    }
+}
+
+pub fn sys_write(args: &SyscallArgs) -> Result<usize, Errno> {
+    if !access_ok(args.arg1, args.arg2, false) {
+        return Err(Errno::BadAddress);
+    }
+    // a chunk at a time, the kernel heap cannot hold any length
+    let mut buf = vec![0u8; args.arg2.min(USER_COPY_CHUNK)];
+    let mut written = 0;
+    while written < args.arg2 {
+        let chunk = &mut buf[..(args.arg2 - written).min(USER_COPY_CHUNK)];
+        copy_from_user(args.arg1 + written, chunk)?;
+        let count = match proc::write(args.arg0 as u8, chunk) {
+            Ok(count) => count,
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        };
+        written += count;
+        if count < chunk.len() {
+            break;
+        }
+    }
+    Ok(written)
 }
 
 pub fn sys_wait_pid(args: &SyscallArgs,context: &mut ProcessContext){
-    let pid = ProcessId(args.arg0 as u16);
-    proc::wait_pid(pid,context);
+    let pid = match args.arg0 as isize {
+        -1 => None,
+        pid @ 1..=0xffff => Some(ProcessId(pid as u16)),
+        // process groups are not supported
+        _ => return context.set_result(Err(Errno::InvalidArgument)),
+    };
+    if args.arg2 & !WNOHANG != 0 {
+        return context.set_result(Err(Errno::InvalidArgument));
+    }
+    proc::wait_pid(pid, args.arg1, args.arg2 & WNOHANG != 0, context);
+}
+
+pub fn sys_thread_create(args: &SyscallArgs) -> Result<usize, Errno> {
+    let entry = args.arg0 as u64;
+    let stack_top = args.arg2 as u64;
+    // the thread starts right below `stack_top`
+    if entry >= USER_SPACE_END || stack_top < 16 || !access_ok(args.arg2 - 16, 16, true) {
+        return Err(Errno::BadAddress);
+    }
+    proc::thread_create(VirtAddr::new(entry), VirtAddr::new(stack_top), args.arg1)
+        .map(|tid| tid.0 as usize)
+}
 
+pub fn sys_thread_join(args: &SyscallArgs, context: &mut ProcessContext) {
+    match pid_arg(args.arg0) {
+        Ok(tid) => proc::thread_join(tid, args.arg1, context),
+        Err(err) => context.set_result(Err(err)),
+    }
+}
+
+pub fn sys_thread_exit(args: &SyscallArgs, context: &mut ProcessContext) {
+    proc::thread_exit(args.arg0 as isize, context);
 }
 
 pub fn sys_get_pid() -> usize{
     proc::get_pid().0 as usize
 }
 
-pub fn sys_read(args: &SyscallArgs) -> usize {
-    // FIXME: just like sys_write
-    let mut buf = unsafe{core::slice::from_raw_parts_mut(args.arg1 as *mut u8, args.arg2)};
-    proc::read(args.arg0 as u8, buf) as usize
-    
+pub fn sys_read(args: &SyscallArgs) -> Result<usize, Errno> {
+    if !access_ok(args.arg1, args.arg2, true) {
+        return Err(Errno::BadAddress);
+    }
+    // a chunk at a time, until a short read
+    let mut buf = vec![0u8; args.arg2.min(USER_COPY_CHUNK)];
+    let mut read = 0;
+    while read < args.arg2 {
+        let chunk = &mut buf[..(args.arg2 - read).min(USER_COPY_CHUNK)];
+        let count = match proc::read(args.arg0 as u8, chunk) {
+            Ok(count) => count,
+            Err(err) if read == 0 => return Err(err),
+            Err(_) => break,
+        };
+        copy_to_user(args.arg1 + read, &chunk[..count])?;
+        read += count;
+        if count < chunk.len() {
+            break;
+        }
+    }
+    Ok(read)
 }
 
 pub fn exit_process(args: &SyscallArgs, context: &mut ProcessContext) {
@@ -65,28 +145,46 @@ pub fn list_process() {
     proc::print_process_list();
 }
 
-pub fn sys_allocate(args: &SyscallArgs) -> usize {
-    let layout = unsafe { (args.arg0 as *const Layout).as_ref().unwrap() };
+pub fn sys_list_proc(args: &SyscallArgs) -> Result<usize, Errno> {
+    let infos = proc::list_proc();
+    let count = infos.len().min(args.arg1);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(infos.as_ptr() as *const u8, count * size_of::<ProcInfo>())
+    };
+    copy_to_user(args.arg0, bytes)?;
+    Ok(infos.len())
+}
+
+// size: arg0, align: arg1 -> ptr: *mut u8
+pub fn sys_allocate(args: &SyscallArgs) -> Result<usize, Errno> {
+    let layout =
+        Layout::from_size_align(args.arg0, args.arg1).map_err(|_| Errno::InvalidArgument)?;
 
     if layout.size() == 0 {
-        return 0;
+        return Err(Errno::InvalidArgument);
     }
 
-    let ret = crate::memory::user::USER_ALLOCATOR
+    crate::memory::user::USER_ALLOCATOR
         .lock()
-        .allocate_first_fit(*layout);
-
-    match ret {
-        Ok(ptr) => ptr.as_ptr() as usize,
-        Err(_) => 0,
-    }
+        .allocate_first_fit(layout)
+        .map(|ptr| ptr.as_ptr() as usize)
+        .map_err(|_| Errno::NoMemory)
 }
 
-pub fn sys_deallocate(args: &SyscallArgs) {
-    let layout = unsafe { (args.arg1 as *const Layout).as_ref().unwrap() };
+// ptr: arg0, size: arg1, align: arg2
+pub fn sys_deallocate(args: &SyscallArgs) -> Result<usize, Errno> {
+    let layout =
+        Layout::from_size_align(args.arg1, args.arg2).map_err(|_| Errno::InvalidArgument)?;
 
     if args.arg0 == 0 || layout.size() == 0 {
-        return;
+        return Err(Errno::InvalidArgument);
+    }
+
+    // only blocks inside the user heap may be handed back to its allocator
+    let heap = USER_HEAP_START..USER_HEAP_START + USER_HEAP_SIZE;
+    match args.arg0.checked_add(layout.size()) {
+        Some(end) if heap.contains(&args.arg0) && end <= heap.end => {}
+        _ => return Err(Errno::InvalidArgument),
     }
 
     let ptr = args.arg0 as *mut u8;
@@ -94,8 +192,10 @@ pub fn sys_deallocate(args: &SyscallArgs) {
     unsafe {
         crate::memory::user::USER_ALLOCATOR
             .lock()
-            .deallocate(core::ptr::NonNull::new_unchecked(ptr), *layout);
+            .deallocate(core::ptr::NonNull::new_unchecked(ptr), layout);
     }
+
+    Ok(0)
 }
 
 pub fn sys_list_app() {
@@ -108,6 +208,18 @@ pub fn sys_time() -> u64 {
     time.hour() as u64 * 3600 + time.minute() as u64 * 60 + time.second() as u64
 }
 
+pub fn sys_sleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    match clock::nanos_to_ticks(args.arg0 as u64) {
+        0 => context.set_result(Ok(0)),
+        ticks => proc::sleep(ticks, context),
+    }
+}
+
+pub fn sys_yield(context: &mut ProcessContext) {
+    context.set_result(Ok(0));
+    proc::yield_now(context);
+}
+
 pub fn sys_fork(context: &mut ProcessContext) {
     proc::fork(context);
 }
@@ -115,48 +227,215 @@ pub fn sys_fork(context: &mut ProcessContext) {
 // op: u8, key: u32, val: usize -> ret: any
 pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
     match args.arg0 {
-        0 => context.set_rax(new_sem(args.arg1 as u32, args.arg2)),
-        1 => context.set_rax(remove_sem(args.arg1 as u32)),
+        0 => context.set_result(new_sem(args.arg1 as u32, args.arg2)),
+        1 => context.set_result(remove_sem(args.arg1 as u32)),
         2 => sem_signal(args.arg1 as u32, context),
         3 => sem_wait(args.arg1 as u32, context),
-        _ => context.set_rax(usize::MAX),
+        _ => context.set_result(Err(Errno::InvalidArgument)),
     }
 }
 
-pub fn list_dir(args: &SyscallArgs) {
-    let path = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
-    };
-    filesystem::ls(path);
-
+pub fn sys_get_dents(args: &SyscallArgs) -> Result<usize, Errno> {
+    let path = user_str(args.arg0, args.arg1)?;
+    if !access_ok(args.arg2, args.arg3, true) {
+        return Err(Errno::BadAddress);
+    }
+    let buf = filesystem::dirents(&path, args.arg4, args.arg3)?;
+    copy_to_user(args.arg2, &buf)?;
+    Ok(buf.len())
 }
 
-pub fn sys_open_file(args: &SyscallArgs) -> usize{
-    let path = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
-    };
-    open_file(path) as usize
+pub fn sys_open_file(args: &SyscallArgs) -> Result<usize, Errno> {
+    let path = user_str(args.arg0, args.arg1)?;
+    open_file(&path).map(|fd| fd as usize)
 }
 
-pub fn sys_close_file(args: &SyscallArgs) -> bool {
+pub fn sys_close_file(args: &SyscallArgs) -> Result<usize, Errno> {
     let fd = args.arg0 as u8;
-    close_file(fd)
+    close_file(fd).map(|_| 0)
 }
 
-pub fn sys_brk(args: &SyscallArgs) -> usize {
+pub fn sys_brk(args: &SyscallArgs) -> Result<usize, Errno> {
     let new_heap_end = if args.arg0 == 0 {
         None
     } else {
-        Some(VirtAddr::new(args.arg0 as u64))
+        Some(VirtAddr::try_new(args.arg0 as u64).map_err(|_| Errno::InvalidArgument)?)
+    };
+    brk(new_heap_end)
+        .map(|new_heap_end| new_heap_end.as_u64() as usize)
+        .ok_or(Errno::NoMemory)
+}
+// op: arg0 -> 0: set mode arg2 for pid arg1, 1: read trace buffer
+pub fn sys_trace(args: &SyscallArgs) -> Result<usize, Errno> {
+    match args.arg0 {
+        0 => {
+            let mode = TraceMode::try_from(args.arg2).map_err(|_| Errno::InvalidArgument)?;
+            let pid = match args.arg1 {
+                0 => get_pid(),
+                pid => pid_arg(pid)?,
+            };
+            set_trace_mode(pid, mode).map(|_| 0)
+        }
+        1 => {
+            if !access_ok(args.arg1, args.arg2, true) {
+                return Err(Errno::BadAddress);
+            }
+            let mut buf = vec![0u8; args.arg2.min(USER_COPY_CHUNK)];
+            let mut read = 0;
+            while read < args.arg2 {
+                let chunk = &mut buf[..(args.arg2 - read).min(USER_COPY_CHUNK)];
+                let count = super::trace::read_buffer(chunk);
+                copy_to_user(args.arg1 + read, &chunk[..count])?;
+                read += count;
+                if count < chunk.len() {
+                    break;
+                }
+            }
+            Ok(read)
+        }
+        _ => Err(Errno::InvalidArgument),
+    }
+}
+
+/// Copy as much of `data` as fits into the user buffer `[ptr, ptr + len)`,
+/// returns the full length so the caller can retry with a larger buffer
+fn copy_truncated(ptr: usize, len: usize, data: &[u8]) -> Result<usize, Errno> {
+    let count = core::cmp::min(len, data.len());
+    copy_to_user(ptr, &data[..count])?;
+    Ok(data.len())
+}
+
+pub fn sys_get_env(args: &SyscallArgs) -> Result<usize, Errno> {
+    let key = user_str(args.arg0, args.arg1)?;
+    let val = proc::env(&key).ok_or(Errno::NotFound)?;
+    copy_truncated(args.arg2, args.arg3, val.as_bytes())
+}
+
+pub fn sys_set_env(args: &SyscallArgs) -> Result<usize, Errno> {
+    let key = user_str(args.arg0, args.arg1)?;
+    let val = user_str(args.arg2, args.arg3)?;
+    proc::set_env(&key, &val).map(|_| 0)
+}
+
+pub fn sys_unset_env(args: &SyscallArgs) -> Result<usize, Errno> {
+    let key = user_str(args.arg0, args.arg1)?;
+    proc::unset_env(&key);
+    Ok(0)
+}
+
+pub fn sys_list_env(args: &SyscallArgs) -> Result<usize, Errno> {
+    let mut block = Vec::new();
+    for var in proc::env_vars() {
+        block.extend_from_slice(var.as_bytes());
+        block.push(0);
+    }
+    copy_truncated(args.arg0, args.arg1, &block)
+}
+
+fn signal(sig: usize) -> Result<Signal, Errno> {
+    Signal::try_from(sig).map_err(|_| Errno::InvalidArgument)
+}
+
+pub fn sys_kill(args: &SyscallArgs) -> Result<usize, Errno> {
+    let sig = match args.arg1 {
+        0 => None,
+        sig => Some(signal(sig)?),
     };
-    match brk(new_heap_end) {
-        Some(new_heap_end) => new_heap_end.as_u64() as usize,
-        None => !0,
+    // like Linux, 0 is the group of the caller and -pgid a whole group,
+    // there is no broadcast to every process with -1
+    match args.arg0 as isize {
+        0 => proc::kill_group(proc::get_pgid(ProcessId(0))?, sig),
+        -1 => Err(Errno::InvalidArgument),
+        pid if pid > 0 => proc::kill(pid_arg(pid as usize)?, sig),
+        pgid => proc::kill_group(pid_arg(pgid.unsigned_abs())?, sig),
     }
-}
\ No newline at end of file
+    .map(|_| 0)
+}
+
+pub fn sys_set_pgid(args: &SyscallArgs) -> Result<usize, Errno> {
+    proc::set_pgid(pid_arg(args.arg0)?, pid_arg(args.arg1)?).map(|_| 0)
+}
+
+pub fn sys_get_pgid(args: &SyscallArgs) -> Result<usize, Errno> {
+    proc::get_pgid(pid_arg(args.arg0)?).map(|pgid| pgid.0 as usize)
+}
+
+pub fn sys_set_sid() -> Result<usize, Errno> {
+    proc::set_sid().map(|sid| sid.0 as usize)
+}
+
+pub fn sys_get_sid(args: &SyscallArgs) -> Result<usize, Errno> {
+    proc::get_sid(pid_arg(args.arg0)?).map(|sid| sid.0 as usize)
+}
+
+pub fn sys_set_priority(args: &SyscallArgs) -> Result<usize, Errno> {
+    let nice = (args.arg1 as isize).clamp(NICE_MIN as isize, NICE_MAX as isize);
+    proc::set_priority(pid_arg(args.arg0)?, nice as i8).map(|_| 0)
+}
+
+pub fn sys_get_priority(args: &SyscallArgs) -> Result<usize, Errno> {
+    // kept positive so it can not be mistaken for an error
+    proc::get_priority(pid_arg(args.arg0)?).map(|nice| (20 - nice as isize) as usize)
+}
+
+pub fn sys_get_rlimit(args: &SyscallArgs) -> Result<usize, Errno> {
+    let res = Rlimit::try_from(args.arg0).map_err(|_| Errno::InvalidArgument)?;
+    // through a pointer, `RLIM_INFINITY` would read as an error in rax
+    let value = proc::rlimit(res);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&value as *const RlimitValue as *const u8, size_of::<RlimitValue>())
+    };
+    copy_to_user(args.arg1, bytes)?;
+    Ok(0)
+}
+
+pub fn sys_get_rusage(args: &SyscallArgs) -> Result<usize, Errno> {
+    let who = RusageWho::try_from(args.arg0).map_err(|_| Errno::InvalidArgument)?;
+    let usage = proc::rusage(who);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&usage as *const Rusage as *const u8, size_of::<Rusage>())
+    };
+    copy_to_user(args.arg1, bytes)?;
+    Ok(0)
+}
+
+pub fn sys_set_rlimit(args: &SyscallArgs) -> Result<usize, Errno> {
+    let res = Rlimit::try_from(args.arg0).map_err(|_| Errno::InvalidArgument)?;
+    let mut value = RlimitValue::INFINITY;
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(&mut value as *mut RlimitValue as *mut u8, size_of::<RlimitValue>())
+    };
+    copy_from_user(args.arg1, bytes)?;
+    proc::set_rlimit(res, value).map(|_| 0)
+}
+
+pub fn sys_set_foreground(args: &SyscallArgs) -> Result<usize, Errno> {
+    proc::set_foreground(pid_arg(args.arg0)?).map(|_| 0)
+}
+
+pub fn sys_get_foreground() -> Result<usize, Errno> {
+    Ok(proc::foreground().map_or(0, |pgid| pgid.0 as usize))
+}
+
+pub fn sys_sigaction(args: &SyscallArgs) -> Result<usize, Errno> {
+    let sig = signal(args.arg0)?;
+    let action = match args.arg1 {
+        SIG_DFL => SigAction::Default,
+        SIG_IGN => SigAction::Ignore,
+        handler => {
+            if !access_ok(handler, 1, false) || !access_ok(args.arg2, 1, false) {
+                return Err(Errno::BadAddress);
+            }
+            SigAction::Handler {
+                handler: handler as u64,
+                restorer: args.arg2 as u64,
+            }
+        }
+    };
+    proc::set_sigaction(sig, action).map(|old| old.as_raw())
+}
+
+pub fn sys_sigprocmask(args: &SyscallArgs) -> Result<usize, Errno> {
+    let how = SigMaskHow::try_from(args.arg0).map_err(|_| Errno::InvalidArgument)?;
+    Ok(proc::set_sigmask(how, args.arg1 as u64) as usize)
+}
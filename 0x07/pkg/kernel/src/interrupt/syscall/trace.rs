@@ -0,0 +1,59 @@
+//! Per-process syscall tracing
+//!
+//! Traced processes get every syscall entry and return value logged with
+//! their pid, either to the console or to a kernel ring buffer that user
+//! space drains through `Syscall::Trace`.
+
+use super::SyscallArgs;
+use crate::proc::ProcessId;
+use alloc::{collections::VecDeque, format};
+use core::fmt::Arguments;
+use spin::Mutex;
+use syscall_def::{errno, Syscall, TraceMode};
+
+const TRACE_BUFFER_SIZE: usize = 0x4000;
+
+/// oldest bytes are dropped once the buffer is full
+static TRACE_BUFFER: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+
+fn log(mode: TraceMode, pid: ProcessId, args: Arguments) {
+    match mode {
+        TraceMode::Off => {}
+        TraceMode::Console => println!("[strace] #{} {}", pid, args),
+        TraceMode::Buffer => {
+            let line = format!("#{} {}\n", pid, args);
+            let mut buffer = TRACE_BUFFER.lock();
+            buffer.extend(line.as_bytes());
+            let overflow = buffer.len().saturating_sub(TRACE_BUFFER_SIZE);
+            buffer.drain(..overflow);
+        }
+    }
+}
+
+pub fn syscall_entry(mode: TraceMode, pid: ProcessId, args: &SyscallArgs) {
+    log(mode, pid, format_args!("{}", args));
+}
+
+/// Log the value returned to `pid`, `None` if the process did not return
+/// directly, e.g. it was blocked or another process was scheduled
+pub fn syscall_return(mode: TraceMode, pid: ProcessId, syscall: &Syscall, ret: Option<usize>) {
+    match ret.map(errno::decode) {
+        Some(Ok(value)) => log(mode, pid, format_args!("{:?} = {:#x}", syscall, value)),
+        Some(Err(err)) => log(
+            mode,
+            pid,
+            format_args!("{:?} = -{} ({})", syscall, err as usize, err),
+        ),
+        None => log(mode, pid, format_args!("{:?} = ? <unfinished>", syscall)),
+    }
+}
+
+/// Move buffered trace output into `buf`, returns the number of bytes
+pub fn read_buffer(buf: &mut [u8]) -> usize {
+    let mut buffer = TRACE_BUFFER.lock();
+    let count = core::cmp::min(buf.len(), buffer.len());
+    for (dst, src) in buf.iter_mut().zip(buffer.drain(..count)) {
+        *dst = src;
+    }
+    count
+}
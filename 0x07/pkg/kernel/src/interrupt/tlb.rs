@@ -0,0 +1,70 @@
+//! TLB shootdown
+//!
+//! A processor that takes access away from user pages, e.g. a fork making
+//! them copy-on-write, flushes its own TLB and sends an IPI to the other
+//! processors that have the same page table loaded, then waits until they
+//! flushed theirs. Processors that load the page table later fetch the new
+//! entries anyway.
+
+use super::apic::*;
+use super::consts::*;
+use super::stats::{self, Source};
+use crate::memory::physical_to_virtual;
+use crate::proc::{self, cpu_id, MAX_CPU_COUNT};
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+use x86_64::instructions::tlb;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const CLEAR: AtomicBool = AtomicBool::new(false);
+
+/// Processors asked to flush their TLB that did not yet
+static PENDING: [AtomicBool; MAX_CPU_COUNT] = [CLEAR; MAX_CPU_COUNT];
+
+pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
+    idt[Interrupts::IrqBase as u8 + Irq::TlbShootdown as u8].set_handler_fn(shootdown_handler);
+}
+
+pub extern "x86-interrupt" fn shootdown_handler(_st: InterruptStackFrame) {
+    stats::count(Source::TlbShootdown);
+    flush_pending();
+    super::ack();
+}
+
+/// Flush the TLB of the current processor if another one asked for it
+///
+/// loops waiting on other processors with interrupts disabled call this,
+/// those may be waiting on this one meanwhile
+pub fn flush_pending() {
+    if PENDING[cpu_id()].swap(false, Ordering::AcqRel) {
+        tlb::flush_all();
+    }
+}
+
+/// Flush the entries of the current page table from the TLB of every
+/// processor, once the page table entries were changed
+///
+/// the other processors only take the IPI with interrupts enabled, so the
+/// caller must not hold any lock they may be spinning on meanwhile.
+pub fn shootdown() {
+    tlb::flush_all();
+    // the new entries are visible before the page tables in use are read
+    fence(Ordering::SeqCst);
+
+    let addr = Cr3::read().0.start_address().as_u64();
+    let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
+    let mut targets = [false; MAX_CPU_COUNT];
+    for cpu in proc::running_page_table(addr) {
+        targets[cpu] = true;
+        PENDING[cpu].store(true, Ordering::Release);
+        // APIC ids are the processor indices, see `smp`
+        lapic.send_ipi(cpu as u32, Interrupts::IrqBase as u8 + Irq::TlbShootdown as u8);
+    }
+
+    let waiting = |cpu: usize| targets[cpu] && PENDING[cpu].load(Ordering::Acquire);
+    while (0..MAX_CPU_COUNT).any(waiting) {
+        flush_pending();
+        core::hint::spin_loop();
+    }
+}
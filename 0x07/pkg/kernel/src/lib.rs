@@ -38,6 +38,7 @@ pub fn init(_boot_info: &'static BootInfo) {
 
     interrupt::init();
     proc::init(_boot_info);
+    interrupt::smp::init(_boot_info);
     x86_64::instructions::interrupts::enable();
     info!("YatSenOS initialized.");
     drivers::filesystem::init();
@@ -19,5 +19,5 @@ pub fn spawn_init() -> proc::ProcessId {
     print!("\x1b[1;1H\x1b[2J");
 
     proc::list_app();
-    proc::spawn("sh").unwrap()
+    proc::spawn("sh", &["sh"], &[]).unwrap()
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ use alloc::boxed::Box;
 use boot::{MemoryMap, MemoryType};
 use x86_64::PhysAddr;
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);
 
@@ -16,7 +17,10 @@ pub struct BootInfoFrameAllocator {
     size: usize,
     used: usize,
     frames: BootInfoFrameIter,
-    recycled: Vec<PhysFrame>
+    recycled: Vec<PhysFrame>,
+    /// Reference counts of frames mapped by several address spaces (after a
+    /// copy-on-write fork), frames that are not in the map have one owner
+    shared: BTreeMap<PhysFrame, usize>,
 }
 
 impl BootInfoFrameAllocator {
@@ -31,6 +35,7 @@ impl BootInfoFrameAllocator {
             frames: create_frame_iter(memory_map),
             used: 0,
             recycled: Vec::new(),
+            shared: BTreeMap::new(),
         }
     }
 
@@ -44,6 +49,15 @@ impl BootInfoFrameAllocator {
     pub fn frames_recycled(&self) -> usize {
         self.recycled.len()
     }
+
+    /// Count one more mapping of `frame`
+    pub fn share_frame(&mut self, frame: PhysFrame) {
+        *self.shared.entry(frame).or_insert(1) += 1;
+    }
+
+    pub fn ref_count(&self, frame: PhysFrame) -> usize {
+        self.shared.get(&frame).copied().unwrap_or(1)
+    }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
@@ -58,9 +72,16 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
 }
 
 impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
-    unsafe fn deallocate_frame(&mut self, _frame: PhysFrame) {
-        // TODO: deallocate frame (not for lab 2)
-        self.recycled.push(_frame);
+    /// Drop one reference to `frame`, it is recycled with the last one
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        if let Some(count) = self.shared.get_mut(&frame) {
+            *count -= 1;
+            if *count == 1 {
+                self.shared.remove(&frame);
+            }
+            return;
+        }
+        self.recycled.push(frame);
     }
 }
 
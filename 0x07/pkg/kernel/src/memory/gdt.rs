@@ -1,6 +1,9 @@
 use core::ptr::addr_of_mut;
 
+use crate::proc::{cpu_id, MAX_CPU_COUNT};
+use alloc::{boxed::Box, vec};
 use lazy_static::lazy_static;
+use spin::Once;
 use x86_64::registers::segmentation::Segment;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
@@ -105,8 +108,9 @@ lazy_static! {
         let code_selector = gdt.append(Descriptor::kernel_code_segment());
         let data_selector = gdt.append(Descriptor::kernel_data_segment());
         let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
-        let user_code_selector = gdt.append(Descriptor::user_code_segment());
+        // `sysret` expects the user data segment right before the user code segment
         let user_data_selector = gdt.append(Descriptor::user_data_segment());
+        let user_code_selector = gdt.append(Descriptor::user_code_segment());
         (
             gdt,
             KernelSelectors {
@@ -122,6 +126,14 @@ lazy_static! {
     };
 }
 
+/// GDT and TSS of an application processor, see `init_ap`
+struct CpuTables {
+    tss: &'static TaskStateSegment,
+    gdt: GlobalDescriptorTable,
+}
+
+static AP_TABLES: [Once<CpuTables>; MAX_CPU_COUNT] = [const { Once::new() }; MAX_CPU_COUNT];
+
 #[derive(Debug)]
 pub struct KernelSelectors {
     pub code_selector: SegmentSelector,
@@ -136,11 +148,56 @@ pub struct UserSelectors{
 }
 
 pub fn init() {
+    load(&GDT.0);
+
+    let mut size = 0;
+
+    for &s in IST_SIZES.iter() {
+        size += s;
+    }
+
+    let (size, unit) = crate::humanized_size(size as u64);
+    info!("Kernel IST Size  : {:>7.*} {}", 3, size, unit);
+
+    info!("GDT Initialized.");
+}
+
+/// Load a GDT and TSS of its own on an application processor
+///
+/// the layout is the same as the one of the bootstrap processor, so are
+/// the selectors, only the stacks differ
+pub fn init_ap(cpu: usize) {
+    let tables = AP_TABLES[cpu].call_once(|| {
+        let mut tss = TaskStateSegment::new();
+        tss.privilege_stack_table[0] = alloc_stack(IST_SIZES[0]);
+        tss.privilege_stack_table[1] = alloc_stack(IST_SIZES[0]);
+        for index in [
+            DOUBLE_FAULT_IST_INDEX,
+            PAGE_FAULT_IST_INDEX,
+            CLOCK_IST_INDEX,
+            SYSCALL_IST_INDEX,
+        ] {
+            tss.interrupt_stack_table[index as usize] = alloc_stack(IST_SIZES[0]);
+        }
+        let tss = Box::leak(Box::new(tss));
+
+        let mut gdt = GlobalDescriptorTable::new();
+        gdt.append(Descriptor::kernel_code_segment());
+        gdt.append(Descriptor::kernel_data_segment());
+        gdt.append(Descriptor::tss_segment(tss));
+        gdt.append(Descriptor::user_data_segment());
+        gdt.append(Descriptor::user_code_segment());
+        CpuTables { tss, gdt }
+    });
+    load(&tables.gdt);
+}
+
+fn load(gdt: &'static GlobalDescriptorTable) {
     use x86_64::instructions::segmentation::{CS, DS, ES, FS, GS, SS};
     use x86_64::instructions::tables::load_tss;
     use x86_64::PrivilegeLevel;
 
-    GDT.0.load();
+    gdt.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
         DS::set_reg(GDT.1.data_selector);
@@ -150,17 +207,12 @@ pub fn init() {
         GS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
         load_tss(GDT.1.tss_selector);
     }
+}
 
-    let mut size = 0;
-
-    for &s in IST_SIZES.iter() {
-        size += s;
-    }
-
-    let (size, unit) = crate::humanized_size(size as u64);
-    info!("Kernel IST Size  : {:>7.*} {}", 3, size, unit);
-
-    info!("GDT Initialized.");
+/// Zeroed stack of `size` bytes on the kernel heap, returns its top
+pub fn alloc_stack(size: usize) -> VirtAddr {
+    let stack = Box::leak(vec![0u8; size].into_boxed_slice());
+    VirtAddr::from_ptr(stack.as_ptr_range().end).align_down(16u64)
 }
 
 pub fn get_selector() -> &'static KernelSelectors {
@@ -170,3 +222,13 @@ pub fn get_selector() -> &'static KernelSelectors {
 pub fn get_user_selector() ->&'static UserSelectors{
     &GDT.2
 }
+
+/// Top of the syscall stack of the current CPU, shared by `int 0x80` and
+/// `syscall` entries
+pub fn syscall_stack_top() -> VirtAddr {
+    let tss = match AP_TABLES[cpu_id()].get() {
+        Some(tables) => tables.tss,
+        None => &TSS,
+    };
+    tss.interrupt_stack_table[SYSCALL_IST_INDEX as usize]
+}
@@ -4,6 +4,9 @@ use x86_64::{registers::rflags::RFlags, structures::idt::InterruptStackFrameValu
 use x86_64::structures::gdt::SegmentSelector;
 use crate::{memory::gdt::get_selector,memory::gdt::get_user_selector, utils::regs::RegistersValue};
 use crate::memory::gdt;
+use syscall_def::Errno;
+use crate::proc::vm::{InitArgs, USER_SPACE_END};
+use x86_64::PrivilegeLevel;
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct ProcessContextValue {
@@ -33,6 +36,29 @@ impl ProcessContext {
         self.value.regs.rax = value;
     }
 
+    /// Encode a syscall result into `rax`, errors become negative values
+    #[inline]
+    pub fn set_result(&mut self, ret: Result<usize, Errno>) {
+        self.set_rax(syscall_def::errno::encode(ret));
+    }
+
+    /// Rewind to the syscall instruction, so that the syscall is issued
+    /// again when the process is resumed
+    #[inline]
+    pub fn restart_syscall(&mut self) {
+        // both `int 0x80` and `syscall` are two bytes long
+        self.value.stack_frame.instruction_pointer -= 2u64;
+    }
+
+    /// Pass the arguments laid out by `ProcessVm::init_args` to the entry,
+    /// as `argc, argv, envc, envp` in rdi, rsi, rdx, rcx
+    pub fn set_entry_args(&mut self, args: &InitArgs) {
+        self.value.regs.rdi = args.argc;
+        self.value.regs.rsi = args.argv as usize;
+        self.value.regs.rdx = args.envc;
+        self.value.regs.rcx = args.envp as usize;
+    }
+
     #[inline]
     pub fn save(&mut self, context: &ProcessContext) {
         self.value = context.as_ref().as_ptr().read();
@@ -59,6 +85,67 @@ impl ProcessContext {
 
         trace!("Init stack frame: {:#?}", &self.stack_frame);
     }
+    /// Start a thread as `entry(arg)`, as if called with the stack at `stack_top`
+    pub fn init_thread_frame(&mut self, entry: VirtAddr, stack_top: VirtAddr, arg: usize) {
+        self.init_stack_frame(entry, stack_top.align_down(16u64) - 8u64);
+        self.value.regs.rdi = arg;
+    }
+
+    #[inline]
+    pub fn instruction_pointer(&self) -> u64 {
+        self.value.stack_frame.instruction_pointer.as_u64()
+    }
+
+    /// Whether the context returns to user mode
+    #[inline]
+    pub fn is_user(&self) -> bool {
+        self.value.stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3
+    }
+
+    /// Enter a signal handler as `handler(sig)` with the stack at `stack_top`
+    pub fn enter_signal_handler(&mut self, handler: u64, stack_top: u64, sig: usize) {
+        self.value.regs.rdi = sig;
+        self.value.stack_frame.instruction_pointer = VirtAddr::new(handler);
+        self.value.stack_frame.stack_pointer = VirtAddr::new(stack_top);
+        // the ABI expects the direction flag to be clear on function entry
+        self.value.stack_frame.cpu_flags.remove(RFlags::DIRECTION_FLAG);
+    }
+
+    /// Restore a context user space handed back, e.g. in a signal frame
+    ///
+    /// only the registers and user controlled flags are taken, the segments
+    /// stay those of user mode, false if `rip` or `rsp` is not a user address
+    pub fn restore_user(&mut self, saved: &ProcessContextValue) -> bool {
+        let frame = &saved.stack_frame;
+        if frame.instruction_pointer.as_u64() >= USER_SPACE_END
+            || frame.stack_pointer.as_u64() >= USER_SPACE_END
+        {
+            return false;
+        }
+
+        let user_flags = RFlags::CARRY_FLAG
+            | RFlags::PARITY_FLAG
+            | RFlags::AUXILIARY_CARRY_FLAG
+            | RFlags::ZERO_FLAG
+            | RFlags::SIGN_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::OVERFLOW_FLAG;
+        let selector = get_user_selector();
+
+        self.value.regs = saved.regs;
+        self.value.stack_frame = InterruptStackFrameValue::new(
+            frame.instruction_pointer,
+            selector.user_code_selector,
+            (frame.cpu_flags & user_flags)
+                | RFlags::IOPL_HIGH
+                | RFlags::IOPL_LOW
+                | RFlags::INTERRUPT_FLAG,
+            frame.stack_pointer,
+            selector.user_data_selector,
+        );
+        true
+    }
+
     pub fn stack_top(&self) -> u64 {
         self.value.stack_frame.stack_pointer.as_u64()
     }
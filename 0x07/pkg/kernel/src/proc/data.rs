@@ -1,4 +1,4 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{collections::BTreeMap, format, sync::Arc};
 use spin::RwLock;
 use x86_64::structures::paging::{
     page::{PageRange, PageRangeInclusive},
@@ -7,7 +7,17 @@ use x86_64::structures::paging::{
 
 use crate::resource::{ResourceSet,Resource};
 use super::*;
-use crate::filesystem::get_rootfs;
+use storage::FileHandle;
+use super::rlimit::ResourceLimits;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use syscall_def::{Errno, Rlimit, RlimitValue};
+
+/// Format `env` as `KEY=VALUE` strings
+pub fn env_vars(env: &BTreeMap<String, String>) -> Vec<String> {
+    env.iter()
+        .map(|(key, val)| format!("{}={}", key, val))
+        .collect()
+}
 
 #[derive(Debug, Clone)]
 pub struct ProcessData {
@@ -15,6 +25,9 @@ pub struct ProcessData {
     pub(super) env: Arc<RwLock<BTreeMap<String, String>>>,
     pub(super) resources: Arc<RwLock<ResourceSet>>,
     pub(super) semaphores: Arc<RwLock<SemaphoreSet>>,
+    // limits and CPU time of the process, summed over its threads
+    pub(super) limits: Arc<RwLock<ResourceLimits>>,
+    pub(super) cpu_ticks: Arc<AtomicUsize>,
 }
 
 impl Default for ProcessData {
@@ -23,6 +36,8 @@ impl Default for ProcessData {
             env: Arc::new(RwLock::new(BTreeMap::new())),
             resources: Arc::new(RwLock::new(ResourceSet::default())),
             semaphores: Arc::new(RwLock::new(SemaphoreSet::default())),
+            limits: Arc::new(RwLock::new(ResourceLimits::default())),
+            cpu_ticks: Arc::new(AtomicUsize::new(0)),
         }
     }
 }
@@ -32,6 +47,43 @@ impl ProcessData {
         Self::default()
     }
 
+    /// Data of a process started with its own copy of `env`
+    pub fn with_env(env: BTreeMap<String, String>) -> Self {
+        Self {
+            env: Arc::new(RwLock::new(env)),
+            ..Self::default()
+        }
+    }
+
+    /// Data of a forked child, the limits are copied and the CPU time
+    /// starts over, the rest stays shared
+    pub fn fork(&self) -> Self {
+        Self {
+            limits: Arc::new(RwLock::new(*self.limits.read())),
+            cpu_ticks: Arc::new(AtomicUsize::new(0)),
+            ..self.clone()
+        }
+    }
+
+    pub fn limits(&self) -> ResourceLimits {
+        *self.limits.read()
+    }
+
+    /// Set the limits on `res` of the process, see `ResourceLimits::set`
+    pub fn set_limit(&self, res: Rlimit, value: RlimitValue) -> Result<(), Errno> {
+        self.limits.write().set(res, value)
+    }
+
+    /// Start with the limits of the process that spawned this one
+    pub fn inherit_limits(&self, limits: ResourceLimits) {
+        *self.limits.write() = limits;
+    }
+
+    /// Account a tick to the process, returns its ticks so far
+    pub fn add_cpu_tick(&self) -> usize {
+        self.cpu_ticks.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
     pub fn env(&self, key: &str) -> Option<String> {
         self.env.read().get(key).cloned()
     }
@@ -40,11 +92,39 @@ impl ProcessData {
         self.env.write().insert(key.into(), val.into());
     }
 
-    pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
-    self.resources.read().read(fd, buf)
+    pub fn unset_env(&mut self, key: &str) -> Option<String> {
+        self.env.write().remove(key)
+    }
+
+    pub fn replace_env(&mut self, env: BTreeMap<String, String>) {
+        *self.env.write() = env;
     }
 
-    pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
+    /// All variables as `KEY=VALUE` strings
+    pub fn env_vars(&self) -> Vec<String> {
+        env_vars(&self.env.read())
+    }
+
+    /// A copy of the environment with the `KEY=VALUE` entries of `envp`
+    /// set on top of it, for a new image
+    pub fn env_with(&self, envp: &[&str]) -> Result<BTreeMap<String, String>, Errno> {
+        let mut env = self.env.read().clone();
+        for var in envp {
+            let (key, val) = var.split_once('=').ok_or(Errno::InvalidArgument)?;
+            env.insert(key.into(), val.into());
+        }
+        Ok(env)
+    }
+
+    pub fn is_console_input(&self, fd: u8) -> bool {
+        self.resources.read().is_console_input(fd)
+    }
+
+    pub fn read(&self, fd: u8, buf: &mut [u8]) -> Result<usize, Errno> {
+        self.resources.read().read(fd, buf)
+    }
+
+    pub fn write(&self, fd: u8, buf: &[u8]) -> Result<usize, Errno> {
         self.resources.read().write(fd, buf)
     }
     pub fn sem_wait(&mut self, key: u32, pid: ProcessId) -> SemaphoreResult {
@@ -61,12 +141,22 @@ impl ProcessData {
     pub fn remove_sem(&mut self, key: u32) -> bool {
         self.semaphores.write().remove(key)
     }
-    pub fn open_file(&self, path: &str) -> u8 {
-        let handle: storage::FileHandle = get_rootfs().fs.open_file(path).unwrap();
-        self.resources.write().open(Resource::File(handle))
+    /// Install `handle` at the lowest free fd, with at most `max_files` open
+    pub fn open_file(&self, handle: FileHandle, max_files: usize) -> Result<u8, Errno> {
+        self.resources.write().open(Resource::File(handle), max_files)
+    }
+
+    /// Open fds with what they refer to
+    pub fn fds(&self) -> Vec<(u8, String)> {
+        self.resources
+            .read()
+            .handles
+            .iter()
+            .map(|(fd, res)| (*fd, res.lock().to_string()))
+            .collect()
     }
 
-    pub fn close_file(&self, fd: u8) -> bool {
+    pub fn close_file(&self, fd: u8) -> Result<(), Errno> {
         self.resources.write().close(fd)
     }
 }
@@ -1,27 +1,31 @@
 use super::*;
 use alloc::sync::Arc;
 use uefi::proto::debug;
-use crate::proc::vm::stack::STACK_INIT_TOP;
 use crate::memory::{
     self,
     allocator::{ALLOCATOR, HEAP_SIZE},
     get_frame_alloc_for_sure, PAGE_SIZE,
 };
 use alloc::{collections::*, format};
+use alloc::boxed::Box;
 use alloc::sync::Weak;
 use spin::{Mutex, RwLock};
 use crate::utils::humanized_size;
+use super::scheduler::Scheduler;
+use super::signal::{self, DefaultAction, SigAction};
+use syscall_def::{Signal, TraceMode};
 
 pub static PROCESS_MANAGER: spin::Once<ProcessManager> = spin::Once::new();
 
-pub fn init(init: Arc<Process>,app:boot::AppListRef) {
+pub fn init(init: Arc<Process>, app: boot::AppListRef, scheduler: &str) {
 
     // FIXME: set init process as Running
     init.write().resume();
 
     // FIXME: set processor's current pid to init's pid
-    processor::set_pid(init.pid());
-    PROCESS_MANAGER.call_once(|| ProcessManager::new(init,app));
+    // the kernel is what the bootstrap processor runs when idle
+    processor::init_bsp(init.pid());
+    PROCESS_MANAGER.call_once(|| ProcessManager::new(init, app, scheduler));
 }
 
 pub fn get_process_manager() -> &'static ProcessManager {
@@ -32,31 +36,203 @@ pub fn get_process_manager() -> &'static ProcessManager {
 
 pub struct ProcessManager {
     processes: RwLock<BTreeMap<ProcessId, Arc<Process>>>,
-    ready_queue: Mutex<VecDeque<ProcessId>>,
+    // orders the ready processes of each CPU, see `scheduler`
+    schedulers: [Mutex<Box<dyn Scheduler>>; MAX_CPU_COUNT],
+    // process each CPU runs, kept alive until the CPU switched away from its
+    // page table
+    on_cpu: [Mutex<Option<Arc<Process>>>; MAX_CPU_COUNT],
     app_list: boot::AppListRef,
     wait_queue: Mutex<BTreeMap<ProcessId, BTreeSet<ProcessId>>>,
+    // threads waiting for any child of their process, by process
+    child_waiters: Mutex<BTreeMap<ProcessId, BTreeSet<ProcessId>>>,
+    // process group owning the console, only it may read stdin
+    foreground: Mutex<Option<ProcessId>>,
+    // reaped processes a CPU still runs, removed once it switched away
+    zombies: Mutex<Vec<ProcessId>>,
+    // sleeping processes by the tick they wake up at
+    sleepers: Mutex<BTreeSet<(u64, ProcessId)>>,
 }
 
 impl ProcessManager {
-    pub fn new(init: Arc<Process>, app: boot::AppListRef) -> Self {
+    pub fn new(init: Arc<Process>, app: boot::AppListRef, scheduler: &str) -> Self {
         let mut processes = BTreeMap::new();
-        let ready_queue = VecDeque::new();
         let pid = init.pid();
 
         trace!("Init {:#?}", init);
+        let schedulers = core::array::from_fn(|_| Mutex::new(scheduler::from_name(scheduler)));
+        info!("Scheduler: {}", schedulers[0].lock().name());
 
         processes.insert(pid, init);
         Self {
             processes: RwLock::new(processes),
-            ready_queue: Mutex::new(ready_queue),
+            schedulers,
+            on_cpu: core::array::from_fn(|_| Mutex::new(None)),
             app_list: app,
             wait_queue: Mutex::new(BTreeMap::new()),
+            child_waiters: Mutex::new(BTreeMap::new()),
+            foreground: Mutex::new(None),
+            zombies: Mutex::new(Vec::new()),
+            sleepers: Mutex::new(BTreeSet::new()),
         }
     }
 
     #[inline]
     pub fn push_ready(&self, pid: ProcessId) {
-        self.ready_queue.lock().push_back(pid);
+        if let Some(proc) = self.get_proc(&pid) {
+            self.enqueue(pid, &mut proc.write());
+        }
+    }
+
+    /// Queue `pid` whose inner the caller already holds
+    ///
+    /// a process goes back to the CPU it last ran on, a new one to the CPU
+    /// with the fewest queued processes. Idle processes never queue, and a
+    /// process is queued at most once.
+    fn enqueue(&self, pid: ProcessId, inner: &mut ProcessInner) {
+        if processor::is_idle(pid) || inner.sched().is_queued() {
+            return;
+        }
+        let cpu = match inner.sched().cpu() {
+            Some(cpu) => cpu,
+            None => (0..MAX_CPU_COUNT)
+                .filter(|&cpu| processor::is_online(cpu))
+                .min_by_key(|&cpu| self.schedulers[cpu].lock().queued())
+                .unwrap_or_else(cpu_id),
+        };
+        inner.sched_mut().set_queued(true);
+        self.schedulers[cpu].lock().push(pid, inner.sched_mut());
+    }
+
+    /// Take the next pid queued on the current CPU, or steal one from
+    /// another CPU if there is none
+    fn pop_ready(&self) -> Option<ProcessId> {
+        let cpu = cpu_id();
+        (0..MAX_CPU_COUNT)
+            .map(|i| (cpu + i) % MAX_CPU_COUNT)
+            .find_map(|cpu| self.schedulers[cpu].lock().pop())
+    }
+
+    fn local_scheduler(&self) -> spin::MutexGuard<'_, Box<dyn Scheduler>> {
+        self.schedulers[cpu_id()].lock()
+    }
+
+    /// Account a timer tick to the current process, true if the scheduler
+    /// wants another process to run
+    pub fn tick(&self, user: bool) -> bool {
+        let proc = self.current();
+        let mut inner = proc.write();
+        // e.g. a kernel thread that exited and waits to be switched away
+        if inner.status() != ProgramStatus::Running {
+            return true;
+        }
+        inner.tick(user);
+        // the idle process gives way as soon as something is ready
+        if processor::is_idle(proc.pid()) {
+            return true;
+        }
+        self.local_scheduler().tick(inner.sched_mut())
+    }
+
+    /// Usage of the current thread, of all threads of its process, or of
+    /// the children they reaped
+    ///
+    /// threads not reaped yet are counted by walking the process table
+    pub fn rusage(&self, who: RusageWho) -> Rusage {
+        let proc = self.current();
+        if who == RusageWho::Thread {
+            return *proc.read().usage();
+        }
+        let tgid = proc.read().tgid();
+        let mut usage = Rusage::default();
+        for proc in self.processes.read().values() {
+            let inner = proc.read();
+            if inner.tgid() != tgid {
+                continue;
+            }
+            usage += match who {
+                RusageWho::Children => *inner.children_usage(),
+                _ => *inner.usage(),
+            };
+        }
+        usage
+    }
+
+    /// Set the nice value of `pid` (0 for the current process)
+    ///
+    /// only the current process and its descendants can be reniced, and
+    /// only to a lower priority, there are no privileged processes
+    pub fn set_priority(&self, pid: ProcessId, nice: i8) -> Result<(), Errno> {
+        let proc = self.live_proc(pid)?;
+        let tgid = self.current().read().tgid();
+        if !Self::descends_from(&proc, tgid) {
+            return Err(Errno::PermissionDenied);
+        }
+        let mut inner = proc.write();
+        if nice < inner.sched().nice() {
+            return Err(Errno::PermissionDenied);
+        }
+        self.local_scheduler().set_nice(inner.sched_mut(), nice);
+        Ok(())
+    }
+
+    /// Set the trace mode of `pid`, only the current process and its
+    /// descendants can be traced
+    pub fn set_trace_mode(&self, pid: ProcessId, mode: TraceMode) -> Result<(), Errno> {
+        let proc = self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?;
+        let tgid = self.current().read().tgid();
+        if !Self::descends_from(&proc, tgid) {
+            return Err(Errno::PermissionDenied);
+        }
+        proc.write().set_trace_mode(mode);
+        Ok(())
+    }
+
+    /// Nice value of `pid` (0 for the current process)
+    pub fn get_priority(&self, pid: ProcessId) -> Result<i8, Errno> {
+        Ok(self.live_proc(pid)?.read().sched().nice())
+    }
+
+    /// Whether `proc` is a thread of the process `tgid` or of one of its
+    /// descendants
+    fn descends_from(proc: &Arc<Process>, tgid: ProcessId) -> bool {
+        let mut next = Some(proc.clone());
+        while let Some(proc) = next {
+            let inner = proc.read();
+            if inner.tgid() == tgid {
+                return true;
+            }
+            next = inner.parent();
+        }
+        false
+    }
+
+    /// Whether the current process may signal `pid`, like `kill` in Linux
+    /// without privileged processes: itself, its descendants and the
+    /// processes of its session
+    pub fn may_signal(&self, pid: ProcessId) -> Result<(), Errno> {
+        let proc = self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?;
+        let (tgid, sid) = {
+            let current = self.current();
+            let inner = current.read();
+            (inner.tgid(), inner.sid())
+        };
+        let same_session = proc.read().sid() == sid;
+        if same_session || Self::descends_from(&proc, tgid) {
+            Ok(())
+        } else {
+            Err(Errno::PermissionDenied)
+        }
+    }
+
+    fn live_proc(&self, pid: ProcessId) -> Result<Arc<Process>, Errno> {
+        let proc = match pid.0 {
+            0 => self.current(),
+            _ => self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?,
+        };
+        if proc.read().status() == ProgramStatus::Dead {
+            return Err(Errno::NoSuchProcess);
+        }
+        Ok(proc)
     }
 
     #[inline]
@@ -69,16 +245,20 @@ impl ProcessManager {
         self.processes.read().get(pid).cloned()
     }
 
+    /// Every process and thread, zombies included, by pid
+    pub fn processes(&self) -> Vec<Arc<Process>> {
+        self.processes.read().values().cloned().collect()
+    }
+
     pub fn current(&self) -> Arc<Process> {
         self.get_proc(&processor::get_pid())
             .expect("No current process")
     }
 
     pub fn save_current(&self, context: &ProcessContext) {
-        // FIXME: update current process's tick count
+        // ticks are accounted by `tick`, on the timer
         let   now_proc=self.current();
         let mut now_proc_inner=now_proc.write();
-        now_proc_inner.tick();
         // FIXME: save current process's context
         now_proc_inner.save(context);
 
@@ -86,49 +266,79 @@ impl ProcessManager {
 
    // filepath: /home/niuxh/YatSenOS-Tutorial-Volume-2/src/0x03/pkg/kernel/src/proc/manager.rs
 pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
+    let cpu = cpu_id();
     // 获取就绪队列并找到下一个可运行的进程
-    let mut next_pid = {
-        let mut queue = self.ready_queue.lock();
-        
-        // 从队列中查找合适的进程
-        let mut selected_pid = None;
-        
-        // 最多检查队列中所有进程
-        let queue_len = queue.len();
-        for _ in 0..queue_len {
-            if let Some(pid) = queue.pop_front() {
-                // 检查进程状态是否有效
-                if let Some(proc) = self.get_proc(&pid) {
-                    if proc.read().status() != ProgramStatus::Dead {
-                        // 找到合适的进程
-                        selected_pid = Some(pid);
-                        break;
-                    }
-                }
-            } else {
-                // 队列已空
-                break;
-            }
+    let next_proc = loop {
+        // 队列锁在取出后立即释放
+        let Some(pid) = self.pop_ready() else {
+            // 如果没有找到可运行进程，运行这个处理器的空闲进程
+            let idle = self.get_proc(&processor::idle_pid()).unwrap();
+            idle.write().restore(context);
+            processor::set_pid(idle.pid());
+            break idle;
+        };
+        // dead or stopped processes may still be queued, another CPU may
+        // have picked the process up in the meantime
+        let Some(proc) = self.get_proc(&pid) else {
+            continue;
+        };
+        let mut inner = proc.write();
+        inner.sched_mut().set_queued(false);
+        if inner.is_ready() {
+            // 恢复选中进程的上下文
+            inner.restore(context);
+            inner.sched_mut().set_cpu(cpu);
+            // 更新处理器的当前PID, before a `kill` waiting for the lock
+            // looks whether the process runs somewhere
+            processor::set_pid(pid);
+            drop(inner);
+            break proc;
         }
-        
-        // 如果没有找到可运行进程，使用内核进程
-        selected_pid.unwrap_or(KERNEL_PID)
-    }; // 队列锁在这里被释放
-
-    // 恢复选中进程的上下文
-    let next_proc = self.get_proc(&next_pid).unwrap();
-    {
-        let mut next_proc_inner = next_proc.write();
-        next_proc_inner.restore(context);
-    } // 写锁在这里被释放
-
-    // 更新处理器的当前PID
-    processor::set_pid(next_pid);
+    };
+    let next_pid = next_proc.pid();
+
+    // the previous page table is no longer loaded, a process that died on
+    // this CPU can let go of its memory now
+    let prev = self.on_cpu[cpu].lock().replace(next_proc);
+    if let Some(prev) = prev.filter(|prev| prev.pid() != next_pid) {
+        let mut inner = prev.write();
+        // like Linux, a process that was still runnable was preempted, yield
+        // included, another CPU may already have picked it up again
+        match inner.status() {
+            ProgramStatus::Dead => inner.release(),
+            ProgramStatus::Ready | ProgramStatus::Running => inner.usage_mut().involuntary_switches += 1,
+            ProgramStatus::Blocked | ProgramStatus::Stopped => inner.usage_mut().voluntary_switches += 1,
+        }
+    }
+
+    let gone: Vec<ProcessId> = {
+        let mut zombies = self.zombies.lock();
+        let (running, gone) = zombies.drain(..).partition(|&pid| processor::is_running(pid));
+        *zombies = running;
+        gone
+    };
+    for pid in gone {
+        self.processes.write().remove(&pid);
+    }
     
     // 返回下一个进程的PID
     next_pid
 }
 
+    /// Process an application processor runs when nothing else is ready,
+    /// sharing the address space of the kernel
+    pub fn new_idle(&self) -> ProcessId {
+        let kproc = self.get_proc(&KERNEL_PID).unwrap();
+        let proc_vm = kproc.read().vm().share();
+        let name = format!("idle/{}", cpu_id());
+        let proc = Process::new(name, None, Some(proc_vm), None)
+            .expect("Failed to allocate an idle pid");
+        proc.write().resume();
+        let pid = proc.pid();
+        self.add_proc(pid, proc);
+        pid
+    }
+
     pub fn spawn_kernel_thread(
         &self,
         entry: VirtAddr,
@@ -138,7 +348,9 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         let kproc = self.get_proc(&KERNEL_PID).unwrap();
         let page_table = kproc.read().clone_page_table();
         let proc_vm = Some(ProcessVm::new(page_table));
-        let proc = Process::new(name, Some(Arc::downgrade(&kproc)), proc_vm, proc_data);
+        let proc = Process::new(name, Some(Arc::downgrade(&kproc)), proc_vm, proc_data)
+            .expect("Failed to allocate a pid for the kernel thread");
+        proc.write().set_group(kproc.read().pgid(), kproc.read().sid());
         let pid=proc.pid();
         // alloc stack for the new process base on pid
         
@@ -149,6 +361,7 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         debug!("process status: {:#?}", proc);
         // FIXME: add to process map
         
+        kproc.write().add_child(proc.clone());
         self.add_proc(proc.pid(), proc);
         // FIXME: push to ready queue
         self.push_ready(pid);
@@ -157,21 +370,57 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         pid
     }
 
+    /// Block the current process until the tick `deadline`
+    pub fn sleep(&self, deadline: u64, context: &ProcessContext) {
+        let pid = processor::get_pid();
+        self.save_current(context);
+        self.block(&pid);
+        self.sleepers.lock().insert((deadline, pid));
+    }
+
+    /// Wake up the processes whose deadline is at or before the tick `now`
+    pub fn wake_sleepers(&self, now: u64) {
+        loop {
+            let pid = {
+                let mut sleepers = self.sleepers.lock();
+                match sleepers.first() {
+                    Some(&(deadline, pid)) if deadline <= now => {
+                        sleepers.pop_first();
+                        pid
+                    }
+                    _ => return,
+                }
+            };
+            self.wake_up(pid, None);
+        }
+    }
+
+    /// Kill the process of the current thread, with all its threads
     pub fn kill_current(&self, ret: isize) {
-        self.kill(processor::get_pid(), ret);
+        let tgid = self.current().read().tgid();
+        self.kill(tgid, ret);
     }
 
     // filepath: [manager.rs](http://_vscodecontentref_/3)
     pub fn handle_page_fault(&self, addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
         // FIXME: handle page fault
         let curr_proc = get_process_manager().current();
-        if !err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
-            && !err_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
-        {
+        // only writes are served, to copy-on-write pages or stack pages not
+        // mapped yet
+        if !err_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
             return false;
         }
         // handle page fault in current process
-        let ret = curr_proc.write().vm_mut().handle_page_fault(addr);
+        let mut inner = curr_proc.write();
+        let ret = inner.handle_page_fault(addr);
+        let remapped = inner.vm_mut().take_remapped();
+        drop(inner);
+        // other threads may still read the frame a copy-on-write page was
+        // copied from. Only user mode writes to such pages, the kernel goes
+        // through the physical mapping, so no locks are held here.
+        if remapped {
+            crate::interrupt::tlb::shootdown();
+        }
         ret
     }
     pub fn kill(&self, pid: ProcessId, ret: isize) {
@@ -181,11 +430,8 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
             warn!("Process #{} not found.", pid);
             return;
         }
-        if let Some(pids) = self.wait_queue.lock().remove(&pid) {
-            for pid in pids {
-                self.wake_up(pid, Some(ret));
-            }
-        }
+        // the pid may be reused before the deadline
+        self.sleepers.lock().retain(|&(_, sleeper)| sleeper != pid);
         let proc = proc.unwrap();
 
         if proc.read().status() == ProgramStatus::Dead {
@@ -196,16 +442,182 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         trace!("Kill {:#?}", &proc);
 
         proc.kill(ret);
-        // consume the Option<ProcessVm> and drop it
+
+        // waiters restart `WaitPid` and collect the exit code themselves,
+        // only wake them once it is there
+        let waiters = self.wait_queue.lock().remove(&pid);
+        let (parent, is_thread) = {
+            let inner = proc.read();
+            (inner.parent(), inner.tgid() != pid)
+        };
+        let parent_waiters = parent.filter(|_| !is_thread).and_then(|parent| {
+            let tgid = parent.read().tgid();
+            self.child_waiters.lock().remove(&tgid)
+        });
+        for waiter in waiters.into_iter().chain(parent_waiters).flatten() {
+            self.wake_up(waiter, None);
+        }
+
+        self.reparent_children(&proc);
+
+        // init collects the exit code of its children right away, others
+        // stay zombies until their parent waits on them
+        if proc.read().parent().is_none_or(|parent| parent.pid() == KERNEL_PID) {
+            self.reap(pid);
+        }
+
+        // the other threads die with the main one, they were handed over
+        // to the kernel above, so nobody has to join them
+        if proc.read().tgid() == pid {
+            for tid in self.threads_of(pid) {
+                self.kill(tid, ret);
+            }
+        }
+    }
+
+    /// Live threads of the process `tgid`, except its main thread
+    fn threads_of(&self, tgid: ProcessId) -> Vec<ProcessId> {
+        self.processes
+            .read()
+            .values()
+            .filter(|proc| {
+                let inner = proc.read();
+                proc.pid() != tgid && inner.tgid() == tgid && inner.status() != ProgramStatus::Dead
+            })
+            .map(|proc| proc.pid())
+            .collect()
+    }
+
+    /// Start a thread of the current process, see `Process::new_thread`
+    pub fn thread_create(
+        &self,
+        entry: VirtAddr,
+        stack_top: VirtAddr,
+        arg: usize,
+    ) -> Result<ProcessId, Errno> {
+        let current = self.current();
+        let tgid = current.read().tgid();
+        let leader = self.get_proc(&tgid).ok_or(Errno::NoSuchProcess)?;
+        let thread = current.new_thread(&leader, entry, stack_top, arg)?;
+        let tid = thread.pid();
+
+        leader.write().add_child(thread.clone());
+        self.add_proc(tid, thread);
+        self.push_ready(tid);
+        Ok(tid)
+    }
+
+    /// Hand the children of the exiting `proc` over to the kernel process
+    fn reparent_children(&self, proc: &Arc<Process>) {
+        let kproc = self.get_proc(&KERNEL_PID).unwrap();
+        let children = proc.write().take_children();
+        for child in children {
+            child.write().set_parent(Arc::downgrade(&kproc));
+            if child.read().status() == ProgramStatus::Dead {
+                self.remove_dead(child.pid());
+            } else {
+                kproc.write().add_child(child);
+            }
+        }
+    }
+
+    /// Take the entry of the dead `pid` out of the table, `None` if it was
+    /// already taken
+    ///
+    /// a CPU may still run it, e.g. until the next tick after it was killed
+    /// there, the entry stays until that CPU switched away
+    fn remove_dead(&self, pid: ProcessId) -> Option<Arc<Process>> {
+        if !processor::is_running(pid) {
+            return self.processes.write().remove(&pid);
+        }
+        let mut zombies = self.zombies.lock();
+        if zombies.contains(&pid) {
+            return None;
+        }
+        zombies.push(pid);
+        drop(zombies);
+        self.get_proc(&pid)
+    }
+
+    /// Free the entry of the zombie `pid`
+    fn reap(&self, pid: ProcessId) {
+        let Some(proc) = self.remove_dead(pid) else {
+            return;
+        };
+        let (parent, is_thread, usage, children_usage) = {
+            let inner = proc.read();
+            (inner.parent(), inner.tgid() != pid, *inner.usage(), *inner.children_usage())
+        };
+        if let Some(parent) = parent {
+            let mut parent = parent.write();
+            parent.remove_child(pid);
+            // a thread counts towards its process, a child towards the parent
+            if is_thread {
+                *parent.usage_mut() += usage;
+            } else {
+                *parent.children_usage_mut() += usage;
+            }
+            *parent.children_usage_mut() += children_usage;
+        }
+        trace!("Reaped process #{}", pid);
+    }
+
+    /// Free the zombie `pid` once its exit code was collected, only a
+    /// thread of the process it is a child or a thread of reaps it
+    pub fn reap_child(&self, pid: ProcessId) {
+        let proc = match self.get_proc(&pid) {
+            Some(proc) => proc,
+            None => return,
+        };
+        let tgid = self.current().read().tgid();
+        let parent = proc.read().parent();
+        let is_child = parent.is_some_and(|parent| parent.read().tgid() == tgid);
+        let is_sibling = pid != proc.read().tgid() && proc.read().tgid() == tgid;
+        if is_child || is_sibling {
+            self.reap(pid);
+        }
+    }
+
+    /// The exited child `pid`, or any exited child if `None`, of the
+    /// process of the current thread, with its exit code
+    ///
+    /// children of all threads of the process count, threads do not,
+    /// `Ok(None)` if the children are still running, `NoChild` if there are
+    /// none to wait for
+    pub fn exited_child(&self, pid: Option<ProcessId>) -> Result<Option<(ProcessId, isize)>, Errno> {
+        let tgid = self.current().read().tgid();
+        let processes = self.processes.read();
+        let group: BTreeSet<ProcessId> = processes
+            .values()
+            .filter(|proc| proc.read().tgid() == tgid)
+            .map(|proc| proc.pid())
+            .collect();
+
+        let mut found = false;
+        for proc in processes.values() {
+            if pid.is_some_and(|pid| pid != proc.pid()) {
+                continue;
+            }
+            let inner = proc.read();
+            let is_child = inner.tgid() == proc.pid()
+                && inner.parent().is_some_and(|parent| group.contains(&parent.pid()));
+            if !is_child {
+                continue;
+            }
+            if inner.status() == ProgramStatus::Dead {
+                return Ok(inner.exit_code().map(|ret| (proc.pid(), ret)));
+            }
+            found = true;
+        }
+        if found { Ok(None) } else { Err(Errno::NoChild) }
     }
     pub fn print_process_list(&self) {
         let mut output =
-            String::from("  PID | PPID | Process Name |  Ticks  | Status | Memory Usage\n");
+            String::from("  PID | PPID | Process Name |   User |    Sys |  Vcsw |  Icsw |   Flt |   Sysc | Nice | Lvl |    Memory | Status\n");
 
+        // dead processes are zombies not yet reaped by their parent
         for (_, p) in self.processes.read().iter() {
-            if p.read().status() != ProgramStatus::Dead {
-                output += format!("{}\n", p).as_str();
-            }
+            output += format!("{}\n", p).as_str();
         }
 
         // TODO: print memory usage of kernel heap
@@ -220,7 +632,9 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         output += &Self::format_usage("Memory", used, total);
         drop(alloc);
 
-        output += format!("Queue  : {:?}\n", self.ready_queue.lock()).as_str();
+        for cpu in (0..MAX_CPU_COUNT).filter(|&cpu| processor::is_online(cpu)) {
+            output += format!("Queue {}: {:?}\n", cpu, self.schedulers[cpu].lock()).as_str();
+        }
 
         output += &processor::print_processors();
 
@@ -252,49 +666,118 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         &self,
         elf: &ElfFile,
         name: String,
+        argv: &[&str],
+        envp: &[&str],
         parent: Option<Weak<Process>>,
         proc_data: Option<ProcessData>,
-    ) -> ProcessId {
+    ) -> Result<ProcessId, Errno> {
         let kproc = self.get_proc(&KERNEL_PID).unwrap();
         let page_table = kproc.read().clone_page_table();
         let proc_vm = Some(ProcessVm::new(page_table));
-        let proc = Process::new(name, parent, proc_vm, proc_data);
+        let parent_proc = parent.as_ref().and_then(|parent| parent.upgrade());
+        let proc = Process::new(name, parent, proc_vm, proc_data)?;
+        if let Some(parent) = &parent_proc {
+            let parent = parent.read();
+            let mut inner = proc.write();
+            inner.set_trace_mode(parent.trace_mode());
+            *inner.sched_mut() = parent.sched().inherit();
+            if let Some(data) = inner.try_data() {
+                data.inherit_limits(parent.limits());
+            }
+            inner.set_group(parent.pgid(), parent.sid());
+        }
         let pid = proc.pid();
         // let stack_top = proc.alloc_init_stack();
         // FIXME: load elf to process pagetable
-        proc.write().load_elf(elf);
+        proc.write().load_elf(elf).map_err(|_| Errno::NoMemory)?;
         // debug!("loading elf to process pagetable");
         // FIXME: alloc new stack for process
         let entry = VirtAddr::new(elf.header.pt2.entry_point());
-        proc.write()
-            .init_stack_frame(entry, VirtAddr::new(STACK_INIT_TOP));
+        proc.write().init_user_entry(entry, argv, envp)?;
         // FIXME: mark process as ready
         proc.write().pause();
 
         trace!("New {:#?}", &proc);
 
         // FIXME: something like kernel thread
+        if let Some(parent) = parent_proc {
+            parent.write().add_child(proc.clone());
+        }
         self.add_proc(pid, proc);
         self.push_ready(pid);
-        pid
+        Ok(pid)
+    }
+
+    /// Replace the image of the current process with `elf`
+    pub fn exec(
+        &self,
+        elf: &ElfFile,
+        name: String,
+        argv: &[&str],
+        envp: &[&str],
+        context: &mut ProcessContext,
+    ) -> Result<(), Errno> {
+        // the new image starts with the main thread only
+        let pid = processor::get_pid();
+        if self.current().read().tgid() != pid {
+            return Err(Errno::NotSupported);
+        }
+
+        let kproc = self.get_proc(&KERNEL_PID).unwrap();
+        let mut proc_vm = ProcessVm::new(kproc.read().clone_page_table());
+        let limits = self.current().read().limits();
+        // a failed load drops the new vm, the process keeps running the old image
+        proc_vm.load_elf(elf).map_err(|_| Errno::NoMemory)?;
+        let args = proc_vm.init_args(argv, envp, &limits)?;
+
+        // the other threads run the old image, nobody will join them. Those
+        // running on other CPUs are stopped before the image is replaced.
+        let threads = self.threads_of(pid);
+        for &tid in &threads {
+            self.kill(tid, 0);
+        }
+        while threads.iter().any(|&tid| processor::is_running(tid)) {
+            // they may wait for this CPU to flush its TLB meanwhile
+            crate::interrupt::tlb::flush_pending();
+            core::hint::spin_loop();
+        }
+        for tid in threads {
+            self.reap(tid);
+        }
+
+        let entry = VirtAddr::new(elf.header.pt2.entry_point());
+        self.current().write().exec(name, proc_vm, entry, args, context);
+        Ok(())
     }
-    pub fn read(&self,fd: u8, buf: &mut [u8]) -> isize{
-        self.current().read().read(fd,buf)
+    /// Read from `fd` of the current process
+    ///
+    /// stdin is reserved to the foreground group, other groups are stopped
+    /// with `SIGTTIN` until they are brought to the foreground
+    pub fn read(&self,fd: u8, buf: &mut [u8]) -> Result<usize, Errno>{
+        let proc = self.current();
+        let pgid = proc.read().pgid();
+        let background = self.foreground_group().is_some_and(|fg| fg != pgid);
+        if background && proc.read().is_console_input(fd) {
+            self.signal_group(pgid, Signal::TerminalInput)?;
+            return Err(Errno::Interrupted);
+        }
+        proc.read().read(fd,buf)
     }
-    pub fn write(&self,fd: u8, buf: &[u8]) -> isize{
+    pub fn write(&self,fd: u8, buf: &[u8]) -> Result<usize, Errno>{
         self.current().write().write(fd,buf)
     }
 
-    pub fn fork(&self) -> Arc<Process>{
+    pub fn fork(&self) -> Result<Arc<Process>, Errno>{
             // FIXME: get current process
             let current_proc = self.current();
             // FIXME: fork to get child
-            let child: Arc<Process> = current_proc.fork();
+            let kproc = self.get_proc(&KERNEL_PID).unwrap();
+            let child: Arc<Process> = current_proc.fork(&kproc.read().vm().page_table)?;
             // FIXME: add child to process list
             self.add_proc(child.pid(), child.clone());
             // FOR DBG: maybe print the process ready queue?
-            debug!("Process ready queue: {:?}", self.ready_queue.lock());
-            child
+            debug!("Process ready queue: {:?}", self.local_scheduler());
+            Ok(child)
     }
     pub fn get_pid(&self) -> ProcessId {
         self.current().pid()
@@ -309,6 +792,13 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
         let entry = wait_queue.entry(pid).or_default();
         entry.insert(processor::get_pid());
     }
+
+    /// Wake the current thread once any child of its process exits
+    pub fn wait_any_child(&self) {
+        let tgid = self.current().read().tgid();
+        let mut waiters = self.child_waiters.lock();
+        waiters.entry(tgid).or_default().insert(processor::get_pid());
+    }
     pub fn get_exit_code(&self, pid: ProcessId)->Option<isize>{
         match self.processes.read().get(&pid) {
             Some(proc) => if proc.read().status() == ProgramStatus::Dead {
@@ -326,6 +816,10 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
     pub fn wake_up(&self, pid: ProcessId, ret: Option<isize>) {
         if let Some(proc) = self.get_proc(&pid) {
             let mut inner: spin::rwlock::RwLockWriteGuard<'_, ProcessInner> = proc.write();
+            // e.g. killed by a signal while waiting
+            if inner.status() != ProgramStatus::Blocked {
+                return;
+            }
             if let Some(ret) = ret {
                 // FIXME: set the return value of the process
                 //        like `context.set_rax(ret as usize)`
@@ -335,14 +829,231 @@ pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
             // FIXME: set the process as ready
             inner.pause();
             // FIXME: push to ready queue
-            self.push_ready(pid);
+            self.enqueue(pid, &mut inner);
         } 
     }
     
-    pub fn open_file(&self, path: &str) -> u8 {
-        self.current().write().open_file(path)
+    /// Send `sig` to `pid`
+    ///
+    /// the default action is taken right away if `pid` is not running,
+    /// anything else is left pending until the process returns to user mode
+    pub fn send_signal(&self, pid: ProcessId, sig: Signal) -> Result<(), Errno> {
+        // the kernel and the idle processes keep the CPUs going
+        if processor::is_idle(pid) {
+            return Err(Errno::PermissionDenied);
+        }
+        let proc = self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?;
+        let mut inner = proc.write();
+        match inner.status() {
+            ProgramStatus::Dead => return Err(Errno::NoSuchProcess),
+            ProgramStatus::Stopped if sig == Signal::Continue => {
+                inner.pause();
+                self.enqueue(pid, &mut inner);
+            }
+            _ => {}
+        }
+
+        if inner.signals().is_ignored(sig) {
+            return Ok(());
+        }
+        inner.signals_mut().post(sig);
+
+        let default = inner.signals().action(sig) == SigAction::Default;
+        if pid == processor::get_pid() || inner.signals().is_blocked(sig) || !default {
+            return Ok(());
+        }
+
+        match signal::default_action(sig) {
+            DefaultAction::Terminate => {
+                // the whole process, not only the thread
+                let tgid = inner.tgid();
+                drop(inner);
+                self.kill(tgid, signal::exit_code(sig));
+            }
+            DefaultAction::Stop if inner.is_ready() => {
+                inner.signals_mut().clear(sig);
+                inner.stop();
+            }
+            // blocked processes stop once they return to user mode
+            DefaultAction::Stop => {}
+            DefaultAction::Continue | DefaultAction::Ignore => inner.signals_mut().clear(sig),
+        }
+        Ok(())
+    }
+
+    /// Act on the pending signals of the current process before `context`
+    /// returns to user mode, this may switch to another process
+    pub fn handle_signals(&self, context: &mut ProcessContext) {
+        if !context.is_user() {
+            return;
+        }
+
+        let proc = self.current();
+        if proc.read().status() == ProgramStatus::Dead {
+            // killed along with another thread of its process
+            self.switch_next(context);
+            return;
+        }
+        loop {
+            let mut inner = proc.write();
+            let sig = match inner.signals_mut().take_pending() {
+                Some(sig) => sig,
+                None => return,
+            };
+
+            let action = match inner.signals().action(sig) {
+                SigAction::Ignore => continue,
+                SigAction::Handler { handler, restorer } => {
+                    if inner.enter_signal_handler(sig, handler, restorer, context) {
+                        return;
+                    }
+                    // the stack is unusable, nothing else can be done
+                    Signal::Segfault
+                }
+                SigAction::Default => sig,
+            };
+            drop(inner);
+
+            match signal::default_action(action) {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Terminate => {
+                    self.kill_current(signal::exit_code(action));
+                    self.switch_next(context);
+                }
+                DefaultAction::Stop => {
+                    self.save_current(context);
+                    proc.write().stop();
+                    self.switch_next(context);
+                }
+            }
+            return;
+        }
+    }
+
+    /// Return from a signal handler of the current process
+    pub fn sigreturn(&self, context: &mut ProcessContext) {
+        if !self.current().write().leave_signal_handler(context) {
+            self.kill_current(signal::exit_code(Signal::Segfault));
+            self.switch_next(context);
+        }
+    }
+
+    /// Send `sig` to every live process of the group `pgid`
+    pub fn signal_group(&self, pgid: ProcessId, sig: Signal) -> Result<(), Errno> {
+        let members = self.group_members(pgid, None);
+        if members.is_empty() {
+            return Err(Errno::NoSuchProcess);
+        }
+        for pid in members {
+            // members may die on the way, e.g. by an earlier signal
+            self.send_signal(pid, sig).ok();
+        }
+        Ok(())
+    }
+
+    /// Live processes of the group `pgid`, only those of session `sid` if given
+    pub(super) fn group_members(&self, pgid: ProcessId, sid: Option<ProcessId>) -> Vec<ProcessId> {
+        self.processes
+            .read()
+            .values()
+            .filter(|proc| {
+                let inner = proc.read();
+                inner.status() != ProgramStatus::Dead
+                    && inner.pgid() == pgid
+                    && sid.is_none_or(|sid| inner.sid() == sid)
+            })
+            .map(|proc| proc.pid())
+            .collect()
+    }
+
+    /// Move `pid` (0 for the current process) into the group `pgid`
+    /// (0 for a new group led by `pid`)
+    ///
+    /// only the current process and its children can be moved, within their
+    /// session, and a session leader stays in its own group
+    pub fn set_pgid(&self, pid: ProcessId, pgid: ProcessId) -> Result<(), Errno> {
+        let current = self.current();
+        let target = match pid.0 {
+            0 => current.clone(),
+            _ => self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?,
+        };
+        let pid = target.pid();
+        let pgid = if pgid.0 == 0 { pid } else { pgid };
+
+        let is_child = target.read().parent().is_some_and(|p| p.pid() == current.pid());
+        if (pid != current.pid() && !is_child) || target.read().status() == ProgramStatus::Dead {
+            return Err(Errno::NoSuchProcess);
+        }
+
+        let sid = current.read().sid();
+        if target.read().sid() != sid || pid == sid {
+            return Err(Errno::PermissionDenied);
+        }
+        if pgid != pid && self.group_members(pgid, Some(sid)).is_empty() {
+            return Err(Errno::PermissionDenied);
+        }
+
+        target.write().set_pgid(pgid);
+        Ok(())
+    }
+
+    /// Process group of `pid`, 0 for the current process
+    pub fn get_pgid(&self, pid: ProcessId) -> Result<ProcessId, Errno> {
+        self.get_group(pid).map(|(pgid, _)| pgid)
+    }
+
+    /// Session of `pid`, 0 for the current process
+    pub fn get_sid(&self, pid: ProcessId) -> Result<ProcessId, Errno> {
+        self.get_group(pid).map(|(_, sid)| sid)
+    }
+
+    fn get_group(&self, pid: ProcessId) -> Result<(ProcessId, ProcessId), Errno> {
+        let proc = match pid.0 {
+            0 => self.current(),
+            _ => self.get_proc(&pid).ok_or(Errno::NoSuchProcess)?,
+        };
+        let inner = proc.read();
+        if inner.status() == ProgramStatus::Dead {
+            return Err(Errno::NoSuchProcess);
+        }
+        Ok((inner.pgid(), inner.sid()))
+    }
+
+    /// Start a new session and group led by the current process
+    pub fn set_sid(&self) -> Result<ProcessId, Errno> {
+        let pid = processor::get_pid();
+        // a group leader would leave its members in another session
+        if !self.group_members(pid, None).is_empty() {
+            return Err(Errno::PermissionDenied);
+        }
+        self.current().write().set_group(pid, pid);
+        Ok(pid)
+    }
+
+    pub fn foreground_group(&self) -> Option<ProcessId> {
+        *self.foreground.lock()
+    }
+
+    /// Give the console to the group `pgid` of the current session
+    pub fn set_foreground_group(&self, pgid: ProcessId) -> Result<(), Errno> {
+        let sid = self.current().read().sid();
+        if self.group_members(pgid, Some(sid)).is_empty() {
+            return Err(Errno::PermissionDenied);
+        }
+        // the pid can not be reused for another group while it owns the console
+        pgid.hold();
+        if let Some(old) = self.foreground.lock().replace(pgid) {
+            old.release();
+        }
+        Ok(())
+    }
+
+    pub fn open_file(&self, path: &str) -> Result<u8, Errno> {
+        // opened before locking, the file system may look at this process
+        let handle = get_rootfs().open_file(path).map_err(|err| fs_errno(&err))?;
+        self.current().write().open_file(handle)
     }
-    pub fn close_file(&self, fd: u8) -> bool {
+    pub fn close_file(&self, fd: u8) -> Result<(), Errno> {
         self.current().write().close_file(fd)
     }
 }
\ No newline at end of file
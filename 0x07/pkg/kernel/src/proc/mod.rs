@@ -5,6 +5,11 @@ mod paging;
 mod pid;
 mod process;
 mod processor;
+mod procfs;
+mod rlimit;
+mod scheduler;
+mod signal;
+mod uaccess;
 pub mod vm;
 pub mod sync;
 
@@ -17,7 +22,7 @@ use manager::*;
 use sync::*;
 use process::*;
 use alloc::sync::Arc;
-pub use processor::get_pid;
+pub use processor::{cpu_id, get_pid, init_apic_id, is_bsp, is_online, running_page_table, MAX_CPU_COUNT};
 use x86::current;
 use alloc::vec::Vec;
 use crate::memory::PAGE_SIZE;
@@ -27,7 +32,15 @@ pub use context::ProcessContext;
 pub use paging::PageTableContext;
 pub use data::ProcessData;
 pub use pid::ProcessId;
-
+pub use procfs::ProcFs;
+pub use uaccess::*;
+pub use signal::SigAction;
+pub use scheduler::{NICE_MAX, NICE_MIN};
+
+use crate::filesystem::{fs_errno, get_rootfs};
+use storage::FileSystem;
+use syscall_def::signal::SigMaskHow;
+use syscall_def::{Errno, ProcInfo, ProcStatus, Rlimit, RlimitValue, Rusage, RusageWho, Signal, TraceMode};
 use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::VirtAddr;
 pub const KERNEL_PID: ProcessId = ProcessId(1);
@@ -37,22 +50,45 @@ pub enum ProgramStatus {
     Running,
     Ready,
     Blocked,
+    Stopped,
     Dead,
 }
 
+impl From<ProgramStatus> for ProcStatus {
+    fn from(status: ProgramStatus) -> Self {
+        match status {
+            ProgramStatus::Running => ProcStatus::Running,
+            ProgramStatus::Ready => ProcStatus::Ready,
+            ProgramStatus::Blocked => ProcStatus::Blocked,
+            ProgramStatus::Stopped => ProcStatus::Stopped,
+            ProgramStatus::Dead => ProcStatus::Dead,
+        }
+    }
+}
+
 /// init process manager
 pub fn init(boot_info: &'static boot::BootInfo) {
     let proc_vm = ProcessVm::new(PageTableContext::new()).init_kernel_vm(&boot_info.kernel_pages);
 
 
     // kernel process
-    let kproc = Process::new(String::from("kernel"),None,Some(proc_vm),None);
+    let kproc = Process::new(String::from("kernel"),None,Some(proc_vm),None)
+        .expect("Failed to allocate the kernel pid");
     
     let app_list = boot_info.loaded_apps.as_ref();
-    manager::init(kproc, app_list);
+    manager::init(kproc, app_list, &boot_info.scheduler);
     info!("Process Manager Initialized.");
 }
 
+/// Put the current application processor online with an idle process of
+/// its own, it picks up processes from the next timer interrupt on
+pub fn init_ap() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let idle = get_process_manager().new_idle();
+        processor::init_cpu(idle);
+    })
+}
+
 pub fn switch(context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // FIXME: switch to the next process
@@ -60,6 +96,11 @@ pub fn switch(context: &mut ProcessContext) {
         //      - handle ready queue update
         //      - restore next process's context
         let manager = manager::get_process_manager();
+        manager.wake_sleepers(crate::interrupt::clock::ticks());
+        // the current process keeps the CPU until its time slice is over
+        if !manager.tick(context.is_user()) {
+            return;
+        }
         manager.save_current(&context);
         
         manager.push_ready(get_pid());
@@ -69,6 +110,28 @@ pub fn switch(context: &mut ProcessContext) {
     });
 }
 
+/// Put the current process to sleep for `ticks` timer ticks
+pub fn sleep(ticks: u64, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = manager::get_process_manager();
+        // the current tick is partly over, never wake up early
+        let deadline = crate::interrupt::clock::ticks() + ticks + 1;
+        context.set_result(Ok(0));
+        manager.sleep(deadline, context);
+        manager.switch_next(context);
+    })
+}
+
+/// Give up the CPU, the current process stays ready
+pub fn yield_now(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = manager::get_process_manager();
+        manager.save_current(context);
+        manager.push_ready(get_pid());
+        manager.switch_next(context);
+    })
+}
+
 pub fn spawn_kernel_thread(entry: fn() -> !, name: String, data: Option<ProcessData>) -> ProcessId {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let entry = VirtAddr::new(entry as usize as u64);
@@ -82,12 +145,48 @@ pub fn print_process_list() {
     })
 }
 
+/// Every process and thread, zombies included, by pid
+pub fn list_proc() -> Vec<ProcInfo> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        manager::get_process_manager()
+            .processes()
+            .iter()
+            .map(|proc| proc.info())
+            .collect()
+    })
+}
+
 pub fn env(key: &str) -> Option<String> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // FIXME: get current process's environment variable
         manager::get_process_manager().current().read().env(key)
     })
 }
+/// Set a variable of the current process, `key` must be non-empty and
+/// must not contain `=` or NUL
+pub fn set_env(key: &str, val: &str) -> Result<(), Errno> {
+    if key.is_empty() || key.contains('=') || key.contains('\0') || val.contains('\0') {
+        return Err(Errno::InvalidArgument);
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        manager::get_process_manager().current().write().set_env(key, val);
+    });
+    Ok(())
+}
+
+pub fn unset_env(key: &str) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        manager::get_process_manager().current().write().unset_env(key);
+    });
+}
+
+/// All variables of the current process as `KEY=VALUE` strings
+pub fn env_vars() -> Vec<String> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        manager::get_process_manager().current().read().env_vars()
+    })
+}
+
 pub fn process_exit(ret: isize) -> ! {
     x86_64::instructions::interrupts::without_interrupts(|| {
         manager::get_process_manager().kill_current(ret);
@@ -124,33 +223,255 @@ pub fn list_app() {
         println!("[+] App list: {}", apps);
     });
 }
-pub fn spawn(name: &str) -> Option<ProcessId> {
-    let app = x86_64::instructions::interrupts::without_interrupts(|| {
+fn find_app(name: &str) -> Result<&'static boot::App<'static>, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
         let app_list = get_process_manager().app_list()?;
         app_list.iter().find(|&app| app.name.eq(name))
-    })?;
+    })
+    .ok_or(Errno::NotFound)
+}
 
-    elf_spawn(name.to_string(), &app.elf)
+/// Read the whole file at `path` on the root filesystem
+fn read_file(path: &str) -> Result<Vec<u8>, Errno> {
+    let mut file = get_rootfs().open_file(path).map_err(|err| fs_errno(&err))?;
+    let mut buf = Vec::with_capacity(file.meta.len);
+    file.read_all(&mut buf).map_err(|err| fs_errno(&err))?;
+    Ok(buf)
 }
-use xmas_elf::ElfFile;
-pub fn elf_spawn(name: String, elf: &ElfFile) -> Option<ProcessId> {
-    let pid = x86_64::instructions::interrupts::without_interrupts(|| {
+
+/// Find the program `path` and run `f` with its name and ELF
+///
+/// the boot app list is searched first, then `path` is read from the
+/// root filesystem, so binaries copied onto the disk can run too
+fn with_program<T>(
+    path: &str,
+    f: impl FnOnce(String, &ElfFile) -> Result<T, Errno>,
+) -> Result<T, Errno> {
+    if let Ok(app) = find_app(path) {
+        return f(app.name.to_string(), &app.elf);
+    }
+
+    let buf = read_file(path)?;
+    let elf = ElfFile::new(&buf).map_err(|_| Errno::NotExecutable)?;
+    if elf.header.pt2.type_().as_type() != header::Type::Executable {
+        return Err(Errno::NotExecutable);
+    }
+
+    let name = path.rsplit('/').next().unwrap_or(path);
+    f(name.to_string(), &elf)
+}
+
+/// Spawn the program at `path` with the arguments `argv`
+///
+/// the child gets a copy of the current environment with the `KEY=VALUE`
+/// entries of `envp` set on top of it
+pub fn spawn(path: &str, argv: &[&str], envp: &[&str]) -> Result<ProcessId, Errno> {
+    with_program(path, |name, elf| elf_spawn(name, elf, argv, envp))
+}
+use xmas_elf::{header, ElfFile};
+pub fn elf_spawn(name: String, elf: &ElfFile, argv: &[&str], envp: &[&str]) -> Result<ProcessId, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let process_name = name.to_lowercase();
-        let parent = Arc::downgrade(&manager.current());
-        let pid = manager.spawn(elf, name, Some(parent), None);
+        let current = manager.current();
+        let env = current.read().env_with(envp)?;
+        let vars = data::env_vars(&env);
+        let vars: Vec<&str> = vars.iter().map(String::as_str).collect();
+        let parent = Arc::downgrade(&current);
+        let data = ProcessData::with_env(env);
+        let pid = manager.spawn(elf, name, argv, &vars, Some(parent), Some(data))?;
 
         debug!("Spawned process: {}#{}", process_name, pid);
-        pid
-    });
+        Ok(pid)
+    })
+}
+
+/// Replace the current process image with the program at `path`
+///
+/// on success `context` is reset to the entry of the new image, and `envp`
+/// is applied to the environment like for `spawn`
+pub fn exec(
+    path: &str,
+    argv: &[&str],
+    envp: &[&str],
+    context: &mut ProcessContext,
+) -> Result<(), Errno> {
+    with_program(path, |name, elf| {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let manager = get_process_manager();
+            let env = manager.current().read().env_with(envp)?;
+            let vars = data::env_vars(&env);
+            let vars: Vec<&str> = vars.iter().map(String::as_str).collect();
+            manager.exec(elf, name, argv, &vars, context)?;
+            manager.current().write().replace_env(env);
+            Ok(())
+        })
+    })
+}
+/// Send `sig` to `pid`, `None` only checks that `pid` is alive
+///
+/// the current process may only signal the processes `may_signal` allows
+pub fn kill(pid: ProcessId, sig: Option<Signal>) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        manager.may_signal(pid)?;
+        match sig {
+            Some(sig) => manager.send_signal(pid, sig),
+            None => manager.get_pgid(pid).map(|_| ()),
+        }
+    })
+}
+
+/// Send `sig` to every process of the group `pgid` the current process may
+/// signal, `PermissionDenied` if it may signal none of them
+pub fn kill_group(pgid: ProcessId, sig: Option<Signal>) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let members = manager.group_members(pgid, None);
+        if members.is_empty() {
+            return Err(Errno::NoSuchProcess);
+        }
+        let allowed: Vec<ProcessId> = members
+            .into_iter()
+            .filter(|&pid| manager.may_signal(pid).is_ok())
+            .collect();
+        if allowed.is_empty() {
+            return Err(Errno::PermissionDenied);
+        }
+        if let Some(sig) = sig {
+            for pid in allowed {
+                // members may die on the way, e.g. by an earlier signal
+                manager.send_signal(pid, sig).ok();
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn set_pgid(pid: ProcessId, pgid: ProcessId) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_pgid(pid, pgid)
+    })
+}
+
+pub fn get_pgid(pid: ProcessId) -> Result<ProcessId, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().get_pgid(pid))
+}
+
+pub fn set_sid() -> Result<ProcessId, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().set_sid())
+}
+
+pub fn get_sid(pid: ProcessId) -> Result<ProcessId, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().get_sid(pid))
+}
+
+pub fn set_foreground(pgid: ProcessId) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_foreground_group(pgid)
+    })
+}
+
+pub fn foreground() -> Option<ProcessId> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().foreground_group()
+    })
+}
+
+/// Set the nice value of `pid` (0 for the current process), clamped to
+/// `NICE_MIN..=NICE_MAX`
+pub fn set_priority(pid: ProcessId, nice: i8) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_priority(pid, nice)
+    })
+}
+
+pub fn get_priority(pid: ProcessId) -> Result<i8, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().get_priority(pid))
+}
+
+/// Count a syscall of the current thread
+pub fn count_syscall() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().write().usage_mut().syscalls += 1;
+    })
+}
+
+pub fn rusage(who: RusageWho) -> Rusage {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().rusage(who))
+}
+
+/// Limits on `res` of the current process
+pub fn rlimit(res: Rlimit) -> RlimitValue {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().limits().value(res)
+    })
+}
+
+/// Set the limits on `res` of the current process, for all its threads,
+/// later children inherit them
+pub fn set_rlimit(res: Rlimit, value: RlimitValue) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let inner = proc.read();
+        inner.try_data().ok_or(Errno::NoSuchProcess)?.set_limit(res, value)
+    })
+}
+
+/// Send `sig` to the foreground group of the console, false if there is none
+pub fn signal_foreground(sig: Signal) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        manager
+            .foreground_group()
+            .is_some_and(|pgid| manager.signal_group(pgid, sig).is_ok())
+    })
+}
+
+pub fn handle_signals(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().handle_signals(context);
+    })
+}
 
-    Some(pid)
+pub fn sigreturn(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().sigreturn(context);
+    })
 }
-pub fn read(fd: u8, buf: &mut [u8]) -> isize {
+
+/// Install `action` for `sig` in the current process, returns the old one
+pub fn set_sigaction(sig: Signal, action: SigAction) -> Result<SigAction, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .write()
+            .signals_mut()
+            .set_action(sig, action)
+    })
+}
+
+/// Change the blocked mask of the current process, returns the old one
+pub fn set_sigmask(how: SigMaskHow, set: u64) -> u64 {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let mut inner = proc.write();
+        let old = inner.signals().blocked();
+        let mask = match how {
+            SigMaskHow::Block => old | set,
+            SigMaskHow::Unblock => old & !set,
+            SigMaskHow::SetMask => set,
+        };
+        inner.signals_mut().set_blocked(mask);
+        old
+    })
+}
+
+pub fn read(fd: u8, buf: &mut [u8]) -> Result<usize, Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().read(fd, buf))
 }
 
-pub fn write(fd: u8, buf: &[u8]) -> isize {
+pub fn write(fd: u8, buf: &[u8]) -> Result<usize, Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().write(fd, buf))
 }
 
@@ -163,6 +484,22 @@ pub fn exit(ret: isize, context: &mut ProcessContext) {
     })
 }
 
+/// Terminate the process of the current thread after a fault in user mode,
+/// as if killed by `sig`, and switch to the next process
+pub fn fault(sig: Signal, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        warn!(
+            "Process #{} killed by {:?} at {:#x}.",
+            manager.current().read().tgid(),
+            sig,
+            context.instruction_pointer()
+        );
+        manager.kill_current(signal::exit_code(sig));
+        manager.switch_next(context);
+    })
+}
+
 #[inline]
 pub fn still_alive(pid: ProcessId) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
@@ -178,13 +515,81 @@ pub fn still_alive(pid: ProcessId) -> bool {
     })
 }
 
-pub fn wait_pid(pid: ProcessId, context: &mut ProcessContext) {
+/// Wait for the child `pid`, or any child if `None`, to exit, store its exit
+/// code at user address `status` and return its pid
+///
+/// the waiter is blocked with the syscall rewound, so it issues `WaitPid`
+/// again once woken up, and finds the exit code this time, with `nohang`
+/// it gets 0 instead of blocking
+pub fn wait_pid(pid: Option<ProcessId>, status: usize, nohang: bool, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        match manager.exited_child(pid) {
+            Err(err) => context.set_result(Err(err)),
+            Ok(Some((child, ret))) => {
+                let ret = match status {
+                    0 => Ok(child.0 as usize),
+                    _ => copy_to_user(status, &ret.to_ne_bytes()).map(|_| child.0 as usize),
+                };
+                if ret.is_ok() {
+                    manager.reap_child(child);
+                }
+                context.set_result(ret);
+            }
+            Ok(None) if nohang => context.set_result(Ok(0)),
+            Ok(None) => {
+                context.restart_syscall();
+                match pid {
+                    Some(pid) => manager.wait_pid(pid),
+                    None => manager.wait_any_child(),
+                }
+                manager.save_current(context);
+                manager.current().write().block();
+                manager.switch_next(context);
+            }
+        }
+    })
+}
+
+/// Start a thread of the current process running `entry(arg)` on the
+/// stack at `stack_top`
+pub fn thread_create(entry: VirtAddr, stack_top: VirtAddr, arg: usize) -> Result<ProcessId, Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().thread_create(entry, stack_top, arg)
+    })
+}
+
+/// Wait for the thread `tid` of the current process to exit and store its
+/// exit code at user address `status`, the thread is freed afterwards
+pub fn thread_join(tid: ProcessId, status: usize, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        if let Some(ret) = manager.get_exit_code(pid) {
-            context.set_rax(ret as usize);
+        let current = manager.current();
+        let sibling = manager.get_proc(&tid).filter(|thread| {
+            let tgid = thread.read().tgid();
+            tgid != tid && tgid == current.read().tgid()
+        });
+        if sibling.is_none() {
+            return context.set_result(Err(Errno::NoSuchProcess));
+        }
+        if tid == current.pid() {
+            return context.set_result(Err(Errno::InvalidArgument));
+        }
+        drop(current);
+
+        if let Some(ret) = manager.get_exit_code(tid) {
+            let ret = match status {
+                0 => Ok(0),
+                _ => copy_to_user(status, &ret.to_ne_bytes()).map(|_| 0),
+            };
+            if ret.is_ok() {
+                manager.reap_child(tid);
+            }
+            context.set_result(ret);
         } else {
-            manager.wait_pid(pid);
+            // issued again once the thread exited, like `WaitPid`
+            context.restart_syscall();
+            manager.wait_pid(tid);
             manager.save_current(context);
             manager.current().write().block();
             manager.switch_next(context);
@@ -192,13 +597,31 @@ pub fn wait_pid(pid: ProcessId, context: &mut ProcessContext) {
     })
 }
 
+/// Exit the current thread only, the main thread takes the whole process
+pub fn thread_exit(ret: isize, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        manager.kill(get_pid(), ret);
+        manager.switch_next(context);
+    })
+}
+
 pub fn fork(context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         // FIXME: save_current as parent
         manager.save_current(&context);
         // FIXME: fork to get child
-        let child = manager.fork();
+        let child = match manager.fork() {
+            Ok(child) => child,
+            Err(err) => {
+                // the parent just keeps running
+                manager.current().write().resume();
+                return context.set_result(Err(err));
+            }
+        };
+        // other threads of the parent may still write to the shared pages
+        crate::interrupt::tlb::shootdown();
         // FIXME: push to child & parent to ready queue
         manager.push_ready(manager.get_pid());
         manager.push_ready(child.pid());
@@ -213,8 +636,8 @@ pub fn sem_wait(key: u32, context: &mut ProcessContext) {
         let pid: ProcessId = processor::get_pid();
         let ret: SemaphoreResult = manager.current().write().sem_wait(key, pid);
         match ret {
-            SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
+            SemaphoreResult::Ok => context.set_result(Ok(0)),
+            SemaphoreResult::NotExist => context.set_result(Err(Errno::NotFound)),
             SemaphoreResult::Block(_pid) => {
                 // FIXME: save, block it, then switch to next
                 //        use `save_current` and `switch_next`
@@ -233,42 +656,63 @@ pub fn sem_signal(key: u32, context: &mut ProcessContext) {
         let manager = get_process_manager();
         let ret = manager.current().write().sem_signal(key);
         match ret {
-            SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
-            SemaphoreResult::WakeUp(pid) => manager.wake_up(pid,None),
+            SemaphoreResult::Ok => context.set_result(Ok(0)),
+            SemaphoreResult::NotExist => context.set_result(Err(Errno::NotFound)),
+            SemaphoreResult::WakeUp(pid) => {
+                // both the waiter and the signaller return successfully
+                manager.wake_up(pid, Some(0));
+                context.set_result(Ok(0));
+            }
             _ => unreachable!(),
         };
     })
 }
-pub fn remove_sem(key: u32) -> usize {
+pub fn remove_sem(key: u32) -> Result<usize, Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let ret = manager.current().write().remove_sem(key);
-        ret as usize
+        ret.then_some(0).ok_or(Errno::NotFound)
     })
 }
 
-pub fn new_sem(key: u32, val: usize) -> usize {
+pub fn new_sem(key: u32, val: usize) -> Result<usize, Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let ret = manager.current().write().new_sem(key, val);
-        ret as usize
+        ret.then_some(0).ok_or(Errno::Exists)
     })
 }
 
 
-pub fn open_file(path: &str) -> u8 {
+pub fn open_file(path: &str) -> Result<u8, Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().open_file(path))
 }
 
-pub fn close_file(fd: u8) -> bool {
+pub fn close_file(fd: u8) -> Result<(), Errno> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().close_file(fd))
 }
 
 
+pub fn current_trace_mode() -> TraceMode {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().trace_mode()
+    })
+}
+
+pub fn set_trace_mode(pid: ProcessId, mode: TraceMode) -> Result<(), Errno> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_trace_mode(pid, mode)
+    })
+}
+
 pub fn brk(addr: Option<VirtAddr>) -> Option<VirtAddr> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // NOTE: `brk` does not need to get write lock
-        get_process_manager().current().read().brk(addr)
+        let end = get_process_manager().current().read().brk(addr);
+        // other threads may still have the unmapped pages cached
+        if addr.is_some() {
+            crate::interrupt::tlb::shootdown();
+        }
+        end
     })
 }
\ No newline at end of file
@@ -8,6 +8,12 @@ use x86_64::{
     VirtAddr,
 };
 
+/// Marks a user page shared read-only by a fork, copied on the first write
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// The lower half of the address space, the upper one belongs to the kernel
+const USER_ENTRIES: usize = 256;
+
 pub struct Cr3RegValue {
     pub addr: PhysFrame,
     pub flags: Cr3Flags,
@@ -57,6 +63,8 @@ impl PageTableContext {
 
     /// Load the page table to Cr3 register.
     pub fn load(&self) {
+        // recorded first, a shootdown after this sees the new page table
+        super::processor::set_page_table(self.reg.addr.start_address().as_u64());
         unsafe { Cr3::write(self.reg.addr, self.reg.flags) }
     }
 
@@ -75,12 +83,83 @@ impl PageTableContext {
         Arc::strong_count(&self.reg)
     }
 
-    pub fn fork(&self) -> Self {
-        // forked process shares the page table
+    /// The same address space, for another thread of the process
+    pub fn share(&self) -> Self {
         Self {
             reg: self.reg.clone(),
         }
     }
+
+    /// Duplicate the address space for a forked child
+    ///
+    /// `kernel` is the page table every process is cloned from, the entries
+    /// it has (e.g. the user heap of the kernel allocator) stay shared. The
+    /// other user tables are copied while the pages are shared: writable ones
+    /// become read-only and `COPY_ON_WRITE` in both address spaces.
+    ///
+    /// Other threads of the parent may still have the writable entries
+    /// cached, the caller shoots them down with `tlb::shootdown` once it
+    /// holds no locks, before the child runs.
+    pub fn fork(&self, kernel: &PageTableContext) -> Self {
+        let child = kernel.clone_level_4();
+        let parent_p4 = unsafe { table_mut(self.reg.addr) };
+        let kernel_p4 = unsafe { table_mut(kernel.reg.addr) };
+        let child_p4 = unsafe { table_mut(child.reg.addr) };
+        let mut alloc = get_frame_alloc_for_sure();
+
+        for i in 0..USER_ENTRIES {
+            let entry = &parent_p4[i];
+            if entry.is_unused() || entry.addr() == kernel_p4[i].addr() {
+                continue;
+            }
+            let table = fork_table(entry.frame().unwrap(), 3, &mut alloc);
+            child_p4[i].set_frame(table, entry.flags());
+        }
+
+        child
+    }
+}
+
+/// The page table held by `frame`, through the physical memory mapping
+///
+/// # Safety
+///
+/// `frame` must hold a page table, that nobody else accesses meanwhile
+unsafe fn table_mut(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *(physical_to_virtual(frame.start_address().as_u64()) as *mut PageTable) }
+}
+
+/// Copy the table in `frame` of the given `level`, the pages it maps are
+/// shared copy-on-write
+fn fork_table(frame: PhysFrame, level: u8, alloc: &mut BootInfoFrameAllocator) -> PhysFrame {
+    let new_frame = alloc
+        .allocate_frame()
+        .expect("Cannot alloc page table for forked process.");
+    let table = unsafe { table_mut(frame) };
+    let new_table = unsafe { table_mut(new_frame) };
+
+    for (entry, new_entry) in table.iter_mut().zip(new_table.iter_mut()) {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            new_entry.set_unused();
+        } else if flags.contains(PageTableFlags::HUGE_PAGE) {
+            // user pages are never huge, leave such mappings shared
+            new_entry.set_addr(entry.addr(), flags);
+        } else if level > 1 {
+            let table = fork_table(entry.frame().unwrap(), level - 1, alloc);
+            new_entry.set_frame(table, flags);
+        } else {
+            let flags = if flags.contains(PageTableFlags::WRITABLE) {
+                (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE
+            } else {
+                flags
+            };
+            entry.set_flags(flags);
+            new_entry.set_addr(entry.addr(), flags);
+            alloc.share_frame(PhysFrame::containing_address(entry.addr()));
+        }
+    }
+    new_frame
 }
 
 impl core::fmt::Debug for PageTableContext {
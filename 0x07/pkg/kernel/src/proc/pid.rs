@@ -1,21 +1,69 @@
-use core::sync::atomic::{AtomicU16, Ordering};
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use syscall_def::Errno;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ProcessId(pub u16);
 
-static COUNTER: AtomicU16 = AtomicU16::new(1);
+const PID_COUNT: usize = u16::MAX as usize + 1;
+
+struct PidTable {
+    /// one bit per pid in use, pid 0 is reserved since syscalls use it for
+    /// "self"
+    bitmap: [u64; PID_COUNT / 64],
+    /// holders of each pid in use: the process itself, the processes using
+    /// it as their group or session id, and the console while it is the
+    /// foreground group
+    refs: BTreeMap<u16, usize>,
+}
+
+static PID_TABLE: Mutex<PidTable> = Mutex::new(PidTable {
+    bitmap: {
+        let mut bitmap = [0; PID_COUNT / 64];
+        bitmap[0] = 1;
+        bitmap
+    },
+    refs: BTreeMap::new(),
+});
 
 impl ProcessId {
-    pub fn new() -> Self {
-         // FIXME: Get a unique PID
-        let pid = COUNTER.fetch_add(1, Ordering::SeqCst);
-        Self(pid)
+    /// Allocate the lowest free pid, held by the new process
+    ///
+    /// fails with `NoSpace` once every pid is in use
+    pub fn new() -> Result<Self, Errno> {
+        let mut table = PID_TABLE.lock();
+        let (index, word) = table
+            .bitmap
+            .iter_mut()
+            .enumerate()
+            .find(|(_, word)| **word != u64::MAX)
+            .ok_or(Errno::NoSpace)?;
+        let bit = word.trailing_ones() as usize;
+        *word |= 1 << bit;
+        let pid = (index * 64 + bit) as u16;
+        table.refs.insert(pid, 1);
+        Ok(Self(pid))
+    }
+
+    /// Keep the pid from being reused while it names a group, a session or
+    /// the foreground group, as POSIX requires
+    pub(super) fn hold(self) {
+        *PID_TABLE.lock().refs.entry(self.0).or_insert(0) += 1;
     }
-}
 
-impl Default for ProcessId {
-    fn default() -> Self {
-        Self::new()
+    /// Drop a hold or the reference of the process itself, the pid goes back
+    /// to the allocator once nothing refers to it anymore
+    pub(super) fn release(self) {
+        let mut table = PID_TABLE.lock();
+        let Some(refs) = table.refs.get_mut(&self.0) else {
+            return;
+        };
+        *refs -= 1;
+        if *refs == 0 {
+            table.refs.remove(&self.0);
+            let index = self.0 as usize;
+            table.bitmap[index / 64] &= !(1 << (index % 64));
+        }
     }
 }
 
@@ -4,15 +4,18 @@ use alloc::sync::Weak;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
 use spin::*;
-use crate::proc::vm::ProcessVm;
+use crate::proc::vm::{InitArgs, ProcessVm};
 use x86_64::structures::paging::mapper::MapToError;
 use x86_64::structures::paging::page::PageRange;
 use x86_64::structures::paging::*;
 use crate::utils::humanized_size;
 
-use crate::proc::vm::stack::STACK_MAX_PAGES;
+use crate::proc::rlimit::{ResourceLimits, CPU_LIMIT_GRACE_TICKS};
+use crate::proc::scheduler::SchedInfo;
+use crate::proc::signal::{self, SignalState};
+use syscall_def::{Rlimit, Rusage, Signal};
+use storage::FileHandle;
 
-#[derive(Clone)]
 pub struct Process {
     pid: ProcessId,
     inner: Arc<RwLock<ProcessInner>>,
@@ -22,13 +25,24 @@ pub struct ProcessInner {
     name: String,
     parent: Option<Weak<Process>>,
     children: Vec<Arc<Process>>,
-    ticks_passed: usize,
+    // counters of this thread (and its reaped threads for a main thread),
+    // and of its reaped children
+    usage: Rusage,
+    children_usage: Rusage,
     status: ProgramStatus,
     context: ProcessContext,
     exit_code: Option<isize>,
     proc_data: Option<ProcessData>,
     proc_vm: Option<ProcessVm>,
-    
+    // syscall tracing, inherited by children
+    trace: TraceMode,
+    signals: SignalState,
+    // job control, a new process starts in the group and session of its parent
+    pgid: ProcessId,
+    sid: ProcessId,
+    sched: SchedInfo,
+    // the process a thread belongs to, the pid itself for the main thread
+    tgid: ProcessId,
 }
 
 impl Process {
@@ -52,11 +66,15 @@ impl Process {
         parent: Option<Weak<Process>>,
         proc_vm: Option<ProcessVm>,
         proc_data: Option<ProcessData>,
-    ) -> Arc<Self> {
+    ) -> Result<Arc<Self>, Errno> {
         let name = name.to_ascii_lowercase();
 
         // create context
-        let pid = ProcessId::new();
+        // running out of pids fails with `NoSpace`
+        let pid = ProcessId::new()?;
+        // leader of its own group and session
+        pid.hold();
+        pid.hold();
         let proc_vm = proc_vm.unwrap_or_else(|| ProcessVm::new(PageTableContext::new()));
 
         let inner = ProcessInner {
@@ -64,20 +82,27 @@ impl Process {
             parent,
             status: ProgramStatus::Ready,
             context: ProcessContext::default(),
-            ticks_passed: 0,
+            usage: Rusage::default(),
+            children_usage: Rusage::default(),
             exit_code: None,
             children: Vec::new(),
             proc_vm: Some(proc_vm),
             proc_data: Some(proc_data.unwrap_or_default()),
+            trace: TraceMode::Off,
+            signals: SignalState::default(),
+            pgid: pid,
+            sid: pid,
+            sched: SchedInfo::default(),
+            tgid: pid,
         };
 
         trace!("New process {}#{} created.", &inner.name, pid);
 
         // create process struct
-        Arc::new(Self {
+        Ok(Arc::new(Self {
             pid,
             inner: Arc::new(RwLock::new(inner)),
-        })
+        }))
     }
 
     pub fn kill(&self, ret: isize) {
@@ -100,20 +125,25 @@ impl Process {
     }
 
 
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    /// Fork with a copy-on-write address space, `kernel` is the page table
+    /// of the kernel process
+    pub fn fork(self: &Arc<Self>, kernel: &PageTableContext) -> Result<Arc<Self>, Errno> {
+        // running out of pids fails with `NoSpace`
+        let child_pid = ProcessId::new()?;
         // FIXME: lock inner as write
         let mut  inner = self.inner.write();
         // FIXME: inner fork with parent weak ref
-        let child_inner = inner.fork(Arc::downgrade(self));
+        let child_inner = inner.fork(Arc::downgrade(self), kernel);
         // FOR DBG: maybe print the child process info
         //          e.g. parent, name, pid, etc.
-        let child_pid = ProcessId::new();
         trace!(
             "Parent {} forked: {}#{}",
             inner.name,
             child_pid,
             child_inner.name
         );
+        let mut child_inner = child_inner;
+        child_inner.tgid = child_pid;
         // FIXME: make the arc of child
         let child_proc = Arc::new(Self{
             pid:child_pid,
@@ -125,23 +155,87 @@ impl Process {
         inner.context.set_rax(child_pid.0 as usize);
         // FIXME: mark the child as ready & return it
         child_proc.inner.write().pause();
-        return child_proc;
-    }
+        return Ok(child_proc);
+    }
+
+
+    /// A new thread of the process of `self`, entering `entry(arg)` with the
+    /// stack at `stack_top`
+    ///
+    /// the thread shares the address space and process data, it is a child
+    /// of the main thread `leader`
+    pub fn new_thread(
+        &self,
+        leader: &Arc<Process>,
+        entry: VirtAddr,
+        stack_top: VirtAddr,
+        arg: usize,
+    ) -> Result<Arc<Self>, Errno> {
+        let inner = self.read();
+        let vm = inner.vm().share();
+        let proc = Process::new(
+            inner.name.clone(),
+            Some(Arc::downgrade(leader)),
+            Some(vm),
+            inner.proc_data.clone(),
+        )?;
+
+        let mut thread = proc.write();
+        thread.trace = inner.trace;
+        thread.signals = inner.signals.fork();
+        thread.set_group(inner.pgid, inner.sid);
+        thread.sched = inner.sched.inherit();
+        thread.tgid = inner.tgid;
+        thread.context.init_thread_frame(entry, stack_top, arg);
+        drop(thread);
 
+        trace!("New thread {}#{} of #{}", inner.name, proc.pid, inner.tgid);
+        Ok(proc)
+    }
 }
 
 impl ProcessInner {
     pub fn init_stack_frame(&mut self, entry: VirtAddr, stack_top: VirtAddr){
         self.context.init_stack_frame(entry, stack_top);
     }
+
+    /// Lay out `argv` and `envp` on the stack and start at `entry` with them
+    pub fn init_user_entry(&mut self, entry: VirtAddr, argv: &[&str], envp: &[&str]) -> Result<(), Errno> {
+        let limits = self.limits();
+        let args = self.vm_mut().init_args(argv, envp, &limits)?;
+        self.context.init_stack_frame(entry, args.stack_top);
+        self.context.set_entry_args(&args);
+        Ok(())
+    }
     pub fn name(&self) -> &str {
         &self.name
     }
     pub fn block(&mut self){
         self.status =  ProgramStatus::Blocked;
     }
-    pub fn tick(&mut self) {
-        self.ticks_passed += 1;
+    /// Account a timer tick to user or kernel time, `user` if the tick
+    /// interrupted ring 3
+    pub fn tick(&mut self, user: bool) {
+        if user {
+            self.usage.user_ticks += 1;
+        } else {
+            self.usage.system_ticks += 1;
+        }
+
+        // `CpuLimit` once the limit of the process is passed, all threads
+        // count, `Kill` if it is still running after the grace period
+        let Some(data) = &self.proc_data else {
+            return;
+        };
+        let ticks = data.add_cpu_tick();
+        let limit = data.limits().get(Rlimit::CpuTicks);
+        if ticks == limit.saturating_add(1) {
+            if !self.signals.is_ignored(Signal::CpuLimit) {
+                self.signals.post(Signal::CpuLimit);
+            }
+        } else if ticks > limit.saturating_add(CPU_LIMIT_GRACE_TICKS) {
+            self.signals.post(Signal::Kill);
+        }
     }
 
     pub fn status(&self) -> ProgramStatus {
@@ -156,6 +250,10 @@ impl ProcessInner {
         self.status = ProgramStatus::Running;
     }
 
+    pub fn stop(&mut self) {
+        self.status = ProgramStatus::Stopped;
+    }
+
     pub fn exit_code(&self) -> Option<isize> {
         self.exit_code
     }
@@ -176,8 +274,23 @@ impl ProcessInner {
         self.proc_vm.as_mut().unwrap()
     }
 
+    /// The address space, `None` once the process has been released
+    pub fn try_vm(&self) -> Option<&ProcessVm> {
+        self.proc_vm.as_ref()
+    }
+
+    /// The shared data, `None` once the process has been released
+    pub fn try_data(&self) -> Option<&ProcessData> {
+        self.proc_data.as_ref()
+    }
+
     pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
-        self.vm_mut().handle_page_fault(addr)
+        let limits = self.limits();
+        let served = self.vm_mut().handle_page_fault(addr, &limits);
+        if served {
+            self.usage.page_faults += 1;
+        }
+        served
     }
 
     /// Save the process's context
@@ -206,52 +319,200 @@ impl ProcessInner {
         self.parent.as_ref().and_then(|p| p.upgrade())
     }
 
+    pub fn set_parent(&mut self, parent: Weak<Process>) {
+        self.parent = Some(parent);
+    }
+
+    pub fn add_child(&mut self, child: Arc<Process>) {
+        self.children.push(child);
+    }
+
+    pub fn remove_child(&mut self, pid: ProcessId) {
+        self.children.retain(|child| child.pid() != pid);
+    }
+
+    pub fn take_children(&mut self) -> Vec<Arc<Process>> {
+        core::mem::take(&mut self.children)
+    }
+
     pub fn kill(&mut self, ret: isize) {
         // FIXME: set exit code
         self.exit_code = Some(ret);
+        // a CPU still runs on the page table, e.g. the process exits or is
+        // killed from another CPU, it is released once switched away
+        let running = self.status == ProgramStatus::Running;
         // FIXME: set status to dead
         self.status = ProgramStatus::Dead;
+        if !running {
+            self.release();
+        }
+    }
+
+    /// Drop the memory and resources of a dead process
+    pub(super) fn release(&mut self) {
         // FIXME: take and drop unused resources
         self.proc_vm.take();
         self.proc_data.take();
     }
     // FIXME: load elf to process pagetable
-    pub fn load_elf(&mut self , elf: &ElfFile){
+    pub fn load_elf(&mut self , elf: &ElfFile) -> Result<(), MapToError<Size4KiB>>{
         self.vm_mut().load_elf(elf)
     }
 
+    /// Replace the process image with `proc_vm`, starting over at `entry`
+    ///
+    /// pid, parent, children and process data (fds, env...) are kept
+    pub fn exec(
+        &mut self,
+        name: String,
+        proc_vm: ProcessVm,
+        entry: VirtAddr,
+        args: InitArgs,
+        context: &mut ProcessContext,
+    ) {
+        self.name = name.to_ascii_lowercase();
 
-    pub fn fork(&mut self, parent: Weak<Process>) -> ProcessInner {
-             
-        // FIXME: calculate the real stack offset
-        let stack_offset_count = ((self.children.len()+1) as u64)*STACK_MAX_PAGES;
+        // leave the old address space before tearing it down
+        proc_vm.page_table.load();
+        self.proc_vm.replace(proc_vm);
+        self.signals.exec();
+
+        *context = ProcessContext::default();
+        context.init_stack_frame(entry, args.stack_top);
+        context.set_entry_args(&args);
+        self.context = *context;
+    }
+
+
+    pub fn fork(&mut self, parent: Weak<Process>, kernel: &PageTableContext) -> ProcessInner {
         // FIXME: fork the process virtual memory struct   
-        let child_vm = self.proc_vm.as_ref().unwrap().fork(stack_offset_count);
+        let child_vm = self.proc_vm.as_ref().unwrap().fork(kernel);
         
-        // FIXME: update `rsp` in interrupt stack frame
+        // the stack stays at the same address in the copied address space
         let mut  children_context: ProcessContext = self.context;
-        let child_stack_top = (self.context.stack_top() & 0xFFFFFFFF)
-            | (child_vm.stack_start().as_u64() & !(0xFFFFFFFF));
-        children_context.update_rsp(child_stack_top);
         // FIXME: set the return value 0 for child with `context.set_rax`
         children_context.set_rax(0);
         // FIXME: clone the process data struct
-        let child_data = self.proc_data.clone();
+        let child_data = self.proc_data.as_ref().map(ProcessData::fork);
+        // the child joins the group and session of its parent
+        self.pgid.hold();
+        self.sid.hold();
         // FIXME: construct the child process inner
         let child_inner = Self { name: self.name.clone(),
                              parent: Some(parent),
                              children: Vec::new(),
-                             ticks_passed: 0, 
+                             usage: Rusage::default(),
+                             children_usage: Rusage::default(),
                              status:ProgramStatus::Ready ,
                              context: children_context,
                              exit_code: None,
                              proc_data: child_data,
-                             proc_vm: Some(child_vm) };
+                             proc_vm: Some(child_vm),
+                             trace: self.trace,
+                             signals: self.signals.fork(),
+                             pgid: self.pgid,
+                             sid: self.sid,
+                             sched: self.sched.inherit(),
+                             tgid: self.tgid };
         
         
         // NOTE: return inner because there's no pid record in inner
         return child_inner;
     }
+    pub fn trace_mode(&self) -> TraceMode {
+        self.trace
+    }
+    pub fn set_trace_mode(&mut self, mode: TraceMode) {
+        self.trace = mode;
+    }
+    pub fn tgid(&self) -> ProcessId {
+        self.tgid
+    }
+
+    pub fn pgid(&self) -> ProcessId {
+        self.pgid
+    }
+
+    pub fn sid(&self) -> ProcessId {
+        self.sid
+    }
+
+    pub fn set_pgid(&mut self, pgid: ProcessId) {
+        pgid.hold();
+        core::mem::replace(&mut self.pgid, pgid).release();
+    }
+
+    /// Join the process group `pgid` of session `sid`
+    pub fn set_group(&mut self, pgid: ProcessId, sid: ProcessId) {
+        self.set_pgid(pgid);
+        sid.hold();
+        core::mem::replace(&mut self.sid, sid).release();
+    }
+
+    pub fn sched(&self) -> &SchedInfo {
+        &self.sched
+    }
+
+    pub fn sched_mut(&mut self) -> &mut SchedInfo {
+        &mut self.sched
+    }
+
+    /// Timer ticks the thread ran for, in user and kernel mode
+    pub fn ticks_passed(&self) -> usize {
+        (self.usage.user_ticks + self.usage.system_ticks) as usize
+    }
+
+    pub fn usage(&self) -> &Rusage {
+        &self.usage
+    }
+
+    pub fn usage_mut(&mut self) -> &mut Rusage {
+        &mut self.usage
+    }
+
+    pub fn children_usage(&self) -> &Rusage {
+        &self.children_usage
+    }
+
+    pub fn children_usage_mut(&mut self) -> &mut Rusage {
+        &mut self.children_usage
+    }
+
+    /// Limits of the process, none once a dead one was released
+    pub fn limits(&self) -> ResourceLimits {
+        self.proc_data
+            .as_ref()
+            .map_or_else(ResourceLimits::default, ProcessData::limits)
+    }
+
+    pub fn signals(&self) -> &SignalState {
+        &self.signals
+    }
+
+    pub fn signals_mut(&mut self) -> &mut SignalState {
+        &mut self.signals
+    }
+
+    /// See `signal::enter_handler`
+    pub fn enter_signal_handler(
+        &mut self,
+        sig: Signal,
+        handler: u64,
+        restorer: u64,
+        context: &mut ProcessContext,
+    ) -> bool {
+        let limits = self.limits();
+        let vm = self.proc_vm.as_mut().unwrap();
+        signal::enter_handler(vm, &limits, &mut self.signals, sig, handler, restorer, context)
+    }
+
+    /// See `signal::leave_handler`
+    pub fn leave_signal_handler(&mut self, context: &mut ProcessContext) -> bool {
+        let limits = self.limits();
+        let vm = self.proc_vm.as_mut().unwrap();
+        signal::leave_handler(vm, &limits, &mut self.signals, context)
+    }
+
     pub fn set_rax(&mut self,ret:usize){
         self.context.set_rax(ret);
     }
@@ -270,11 +531,46 @@ impl ProcessInner {
         self.proc_data.as_mut().unwrap().remove_sem(key)
     }
 
-    pub fn open_file(&mut self, path: &str) -> u8 {
-        self.proc_data.as_mut().unwrap().open_file(path)
+    pub fn open_file(&mut self, handle: FileHandle) -> Result<u8, Errno> {
+        let max_files = self.limits().get(Rlimit::OpenFiles);
+        self.proc_data.as_mut().unwrap().open_file(handle, max_files)
     }
     pub fn brk(&self,addr: Option<VirtAddr>) -> Option<VirtAddr>{
-        self.proc_vm.as_ref().unwrap().brk(addr)
+        self.proc_vm.as_ref().unwrap().brk(addr, &self.limits())
+    }
+}
+
+impl Process {
+    /// A snapshot for `ListProc`
+    pub fn info(&self) -> ProcInfo {
+        let inner = self.inner.read();
+        let mut info = ProcInfo::default();
+        info.pid = self.pid.0;
+        info.ppid = inner.parent().map_or(0, |p| p.pid.0);
+        info.tgid = inner.tgid.0;
+        info.pgid = inner.pgid.0;
+        info.status = ProcStatus::from(inner.status) as u8;
+        info.nice = inner.sched.nice();
+        info.user_ticks = inner.usage.user_ticks;
+        info.system_ticks = inner.usage.system_ticks;
+        info.memory = inner.proc_vm.as_ref().map_or(0, |vm| vm.resident_pages() * PAGE_SIZE);
+        info.exit_code = inner.exit_code.unwrap_or(0) as i64;
+        info.set_name(&inner.name);
+        info
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // the last reference goes away once the zombie has been reaped
+        self.pid.release();
+    }
+}
+
+impl Drop for ProcessInner {
+    fn drop(&mut self) {
+        self.pgid.release();
+        self.sid.release();
     }
 }
 
@@ -314,7 +610,7 @@ impl core::fmt::Debug for Process {
             .field("name", &inner.name)
             .field("parent", &inner.parent().map(|p| p.pid))
             .field("status", &inner.status)
-            .field("ticks_passed", &inner.ticks_passed)
+            .field("usage", &inner.usage)
             .field("children", &inner.children.iter().map(|c| c.pid.0))
             .field("status", &inner.status)
             .field("context", &inner.context)
@@ -329,11 +625,18 @@ impl core::fmt::Display for Process {
         let (size, unit) = humanized_size(inner.proc_vm.as_ref().map_or(0, |vm| vm.memory_usage()));
         write!(
             f,
-            " #{:-3} | #{:-3} | {:12} | {:7} | {:>5.1} {} | {:?}",
+            " #{:-3} | #{:-3} | {:12} | {:>6} | {:>6} | {:>5} | {:>5} | {:>5} | {:>6} | {:>4} | {:>3} | {:>5.1} {} | {:?}",
             self.pid.0,
             inner.parent().map(|p| p.pid.0).unwrap_or(0),
             inner.name,
-            inner.ticks_passed,
+            inner.usage.user_ticks,
+            inner.usage.system_ticks,
+            inner.usage.voluntary_switches,
+            inner.usage.involuntary_switches,
+            inner.usage.page_faults,
+            inner.usage.syscalls,
+            inner.sched.nice(),
+            inner.sched.level(),
             size,
             unit,
             inner.status
@@ -1,24 +1,107 @@
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicUsize, Ordering};
 
 use crate::proc::ProcessId;
 use alloc::{string::String, vec::Vec};
-use x86::cpuid::CpuId;
+use core::ptr::read_volatile;
+use x86_64::registers::control::Cr3;
 
-const MAX_CPU_COUNT: usize = 4;
+pub const MAX_CPU_COUNT: usize = 4;
 
 #[allow(clippy::declare_interior_mutable_const)]
 const EMPTY: Processor = Processor::new(); // means no process
 
 static PROCESSORS: [Processor; MAX_CPU_COUNT] = [EMPTY; MAX_CPU_COUNT];
 
+/// Index of the processor that booted the kernel
+static BSP: AtomicUsize = AtomicUsize::new(0);
+
+/// Virtual address of the ID register of the local APIC, the same on every
+/// processor, each reads its own APIC ID there
+static APIC_ID_REG: AtomicU64 = AtomicU64::new(0);
+
+/// Take the processor indices from the local APIC mapped at `lapic`, once
+/// the bootstrap processor set it up
+pub fn init_apic_id(lapic: u64) {
+    APIC_ID_REG.store(lapic + 0x20, Ordering::Relaxed);
+}
+
+/// Index of the current processor, its APIC ID
+///
+/// a single load from the local APIC, `cpuid` is far slower on this hot
+/// path. Before the local APIC is set up only the bootstrap processor
+/// runs, as processor 0.
+#[inline]
+pub fn cpu_id() -> usize {
+    match APIC_ID_REG.load(Ordering::Relaxed) {
+        0 => 0,
+        reg => (unsafe { read_volatile(reg as *const u32) } >> 24) as usize,
+    }
+}
+
 /// Returns the current processor based on the current APIC ID
 fn current() -> &'static Processor {
-    let cpuid = CpuId::new()
-        .get_feature_info()
-        .unwrap()
-        .initial_local_apic_id() as usize;
+    &PROCESSORS[cpu_id()]
+}
+
+/// Mark the current processor as the bootstrap one, running `idle` when
+/// there is nothing else to do
+pub fn init_bsp(idle: ProcessId) {
+    BSP.store(cpu_id(), Ordering::Relaxed);
+    init_cpu(idle);
+}
+
+/// Bring the current processor online, running `idle` when there is nothing
+/// else to do
+pub fn init_cpu(idle: ProcessId) {
+    let cpu = current();
+    cpu.idle.store(idle.0, Ordering::Relaxed);
+    cpu.set_pid(idle);
+    set_page_table(Cr3::read().0.start_address().as_u64());
+    cpu.online.store(true, Ordering::Release);
+}
+
+#[inline]
+pub fn is_bsp() -> bool {
+    cpu_id() == BSP.load(Ordering::Relaxed)
+}
+
+pub fn is_online(cpu: usize) -> bool {
+    PROCESSORS
+        .get(cpu)
+        .is_some_and(|p| p.online.load(Ordering::Acquire))
+}
+
+/// Idle process of the current processor
+#[inline]
+pub fn idle_pid() -> ProcessId {
+    ProcessId(current().idle.load(Ordering::Relaxed))
+}
+
+/// Whether `pid` is the current process of any processor
+pub fn is_running(pid: ProcessId) -> bool {
+    PROCESSORS.iter().any(|p| p.get_pid() == Some(pid))
+}
 
-    &PROCESSORS[cpuid]
+/// Whether `pid` is the idle process of a processor, those never queue
+pub fn is_idle(pid: ProcessId) -> bool {
+    PROCESSORS
+        .iter()
+        .any(|p| p.idle.load(Ordering::Relaxed) == pid.0)
+}
+
+/// Record the page table the current processor is about to load
+#[inline]
+pub fn set_page_table(addr: u64) {
+    current().page_table.store(addr, Ordering::SeqCst);
+}
+
+/// Online processors other than the current one that have the page table
+/// at `addr` loaded
+pub fn running_page_table(addr: u64) -> impl Iterator<Item = usize> {
+    let this = cpu_id();
+    (0..MAX_CPU_COUNT).filter(move |&cpu| {
+        cpu != this && is_online(cpu) && PROCESSORS[cpu].page_table.load(Ordering::SeqCst) == addr
+    })
 }
 
 pub fn print_processors() -> String {
@@ -35,11 +118,22 @@ pub fn print_processors() -> String {
 }
 
 /// Processor holds the current process id
-pub struct Processor(AtomicU16);
+pub struct Processor {
+    pid: AtomicU16,
+    idle: AtomicU16,
+    online: AtomicBool,
+    // physical address of the loaded page table
+    page_table: AtomicU64,
+}
 
 impl Processor {
     pub const fn new() -> Self {
-        Self(AtomicU16::new(0))
+        Self {
+            pid: AtomicU16::new(0),
+            idle: AtomicU16::new(0),
+            online: AtomicBool::new(false),
+            page_table: AtomicU64::new(0),
+        }
     }
 }
 
@@ -56,17 +150,17 @@ pub fn get_pid() -> ProcessId {
 impl Processor {
     #[inline]
     pub fn is_free(&self) -> bool {
-        self.0.load(Ordering::Relaxed) == 0
+        self.pid.load(Ordering::Relaxed) == 0
     }
 
     #[inline]
     pub fn set_pid(&self, pid: ProcessId) {
-        self.0.store(pid.0, Ordering::Relaxed);
+        self.pid.store(pid.0, Ordering::Relaxed);
     }
 
     #[inline]
     pub fn get_pid(&self) -> Option<ProcessId> {
-        let pid = self.0.load(Ordering::Relaxed);
+        let pid = self.pid.load(Ordering::Relaxed);
         if pid == 0 {
             None
         } else {
@@ -0,0 +1,269 @@
+//! Read-only view of the kernel state, mounted at `/proc`
+//!
+//! ```text
+//! /proc/<pid>/status    name, ids, state and usage counters
+//! /proc/<pid>/maps      mapped regions of the address space
+//! /proc/<pid>/environ   environment, NUL separated
+//! /proc/<pid>/fd        open fds with what they refer to
+//! /proc/meminfo         physical frames
+//! /proc/interrupts      interrupts by source and processor
+//! /proc/uptime          seconds since boot and spent idle
+//! ```
+//!
+//! Contents are generated when a file is opened, reads see that snapshot.
+
+use super::*;
+use crate::interrupt::{clock, stats};
+use crate::memory::get_frame_alloc_for_sure;
+use alloc::boxed::Box;
+use alloc::format;
+use core::fmt::Write as _;
+use storage::*;
+
+const PROC_FILES: [&str; 4] = ["status", "maps", "environ", "fd"];
+const KERNEL_FILES: [&str; 3] = ["meminfo", "interrupts", "uptime"];
+
+#[derive(Debug, Default)]
+pub struct ProcFs;
+
+/// What a path inside `/proc` names
+enum Node<'a> {
+    Root,
+    ProcDir(Arc<Process>),
+    ProcFile(Arc<Process>, &'a str),
+    KernelFile(&'a str),
+}
+
+impl ProcFs {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn lookup<'a>(&self, path: &'a str) -> Result<Node<'a>> {
+        let mut parts = path.split(PATH_SEPARATOR).filter(|part| !part.is_empty());
+        let node = match (parts.next(), parts.next()) {
+            (None, _) => Node::Root,
+            (Some(name), None) if KERNEL_FILES.contains(&name) => Node::KernelFile(name),
+            (Some(pid), file) => {
+                let proc = pid
+                    .parse::<u16>()
+                    .ok()
+                    .and_then(|pid| without_interrupts(|| get_process_manager().get_proc(&ProcessId(pid))))
+                    .ok_or(FsError::FileNotFound)?;
+                match file {
+                    None => Node::ProcDir(proc),
+                    Some(file) if PROC_FILES.contains(&file) => Node::ProcFile(proc, file),
+                    Some(_) => return Err(FsError::FileNotFound),
+                }
+            }
+        };
+        match parts.next() {
+            Some(_) => Err(FsError::FileNotFound),
+            None => Ok(node),
+        }
+    }
+}
+
+fn dir(name: String) -> Metadata {
+    Metadata::new(name, FileType::Directory, 0, None, None, None)
+}
+
+fn file(name: &str, len: usize) -> Metadata {
+    Metadata::new(name.into(), FileType::File, len, None, None, None)
+}
+
+fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    x86_64::instructions::interrupts::without_interrupts(f)
+}
+
+impl FileSystem for ProcFs {
+    fn read_dir(&self, path: &str) -> Result<Box<dyn Iterator<Item = Metadata> + Send>> {
+        let entries: Vec<Metadata> = match self.lookup(path)? {
+            Node::Root => {
+                let pids = without_interrupts(|| get_process_manager().processes());
+                pids.iter()
+                    .map(|proc| dir(proc.pid().0.to_string()))
+                    .chain(KERNEL_FILES.iter().map(|name| file(name, 0)))
+                    .collect()
+            }
+            Node::ProcDir(_) => PROC_FILES.iter().map(|name| file(name, 0)).collect(),
+            _ => return Err(FsError::NotADirectory),
+        };
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> Result<FileHandle> {
+        let (name, data) = match self.lookup(path)? {
+            Node::ProcFile(proc, name) => (name, without_interrupts(|| proc_file(&proc, name))),
+            Node::KernelFile(name) => (name, without_interrupts(|| kernel_file(name))),
+            _ => return Err(FsError::NotAFile),
+        };
+        let meta = file(name, data.len());
+        Ok(FileHandle::new(meta, Box::new(Snapshot { data, pos: 0 })))
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        Ok(match self.lookup(path)? {
+            Node::Root => dir(String::new()),
+            Node::ProcDir(proc) => dir(proc.pid().0.to_string()),
+            Node::ProcFile(_, name) | Node::KernelFile(name) => file(name, 0),
+        })
+    }
+
+    fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.lookup(path).is_ok())
+    }
+}
+
+fn proc_file(proc: &Process, name: &str) -> Vec<u8> {
+    let inner = proc.read();
+    let mut out = String::new();
+    match name {
+        "status" => {
+            let usage = inner.usage();
+            let rss = inner.try_vm().map_or(0, |vm| vm.resident_pages());
+            let _ = write!(
+                out,
+                "Name:\t{}\nState:\t{:?}\nPid:\t{}\nTgid:\t{}\nPPid:\t{}\nPgid:\t{}\nSid:\t{}\n\
+                 Nice:\t{}\nVmRSS:\t{} kB\nUserTicks:\t{}\nSysTicks:\t{}\n\
+                 VoluntarySwitches:\t{}\nInvoluntarySwitches:\t{}\nPageFaults:\t{}\nSyscalls:\t{}\n",
+                inner.name(),
+                inner.status(),
+                proc.pid().0,
+                inner.tgid().0,
+                inner.parent().map_or(0, |p| p.pid().0),
+                inner.pgid().0,
+                inner.sid().0,
+                inner.sched().nice(),
+                rss * PAGE_SIZE / 1024,
+                usage.user_ticks,
+                usage.system_ticks,
+                usage.voluntary_switches,
+                usage.involuntary_switches,
+                usage.page_faults,
+                usage.syscalls,
+            );
+        }
+        "maps" => {
+            for region in inner.try_vm().map(|vm| vm.regions()).unwrap_or_default() {
+                let _ = writeln!(
+                    out,
+                    "{:012x}-{:012x} r{}{} {}",
+                    region.start.as_u64(),
+                    region.end.as_u64(),
+                    if region.writable { 'w' } else { '-' },
+                    if region.executable { 'x' } else { '-' },
+                    region.name
+                );
+            }
+        }
+        "environ" => {
+            for var in inner.try_data().map(|data| data.env_vars()).unwrap_or_default() {
+                out.push_str(&var);
+                out.push('\0');
+            }
+        }
+        "fd" => {
+            for (fd, res) in inner.try_data().map(|data| data.fds()).unwrap_or_default() {
+                let _ = writeln!(out, "{}\t{}", fd, res);
+            }
+        }
+        _ => unreachable!(),
+    }
+    out.into_bytes()
+}
+
+fn kernel_file(name: &str) -> Vec<u8> {
+    let mut out = String::new();
+    match name {
+        "meminfo" => {
+            let alloc = get_frame_alloc_for_sure();
+            let total = alloc.frames_total();
+            let used = alloc.frames_used() - alloc.frames_recycled();
+            drop(alloc);
+            let kb = PAGE_SIZE as usize / 1024;
+            let _ = write!(
+                out,
+                "MemTotal:\t{} kB\nMemUsed:\t{} kB\nMemFree:\t{} kB\nFramesTotal:\t{}\nFramesUsed:\t{}\n",
+                total * kb,
+                used * kb,
+                total.saturating_sub(used) * kb,
+                total,
+                used,
+            );
+        }
+        "interrupts" => {
+            let cpus: Vec<usize> = (0..MAX_CPU_COUNT).filter(|&cpu| is_online(cpu)).collect();
+            let _ = write!(out, "{:>11}", "");
+            for cpu in &cpus {
+                let _ = write!(out, " {:>10}", format!("CPU{}", cpu));
+            }
+            out.push('\n');
+            for source in stats::Source::ALL {
+                let _ = write!(out, "{:>10}:", source.name());
+                for &cpu in &cpus {
+                    let _ = write!(out, " {:>10}", stats::get(source, cpu));
+                }
+                out.push('\n');
+            }
+        }
+        "uptime" => {
+            let idle_ticks: usize = get_process_manager()
+                .processes()
+                .iter()
+                .filter(|proc| processor::is_idle(proc.pid()))
+                .map(|proc| proc.read().ticks_passed())
+                .sum();
+            let uptime = clock::now_nanos() / 10_000_000;
+            let idle = idle_ticks as u64 * clock::tick_nanos() / 10_000_000;
+            let _ = writeln!(
+                out,
+                "{}.{:02} {}.{:02}",
+                uptime / 100,
+                uptime % 100,
+                idle / 100,
+                idle % 100
+            );
+        }
+        _ => unreachable!(),
+    }
+    out.into_bytes()
+}
+
+/// Contents of an opened file
+struct Snapshot {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for Snapshot {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Write for Snapshot {
+    fn write(&mut self, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Snapshot {
+    fn seek(&mut self, pos: SeekFrom) -> Result<usize> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.data.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or(FsError::InvalidOffset)?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,70 @@
+//! Resource limits of a process
+//!
+//! Limits belong to the whole process, its threads share them through
+//! `ProcessData`. They are copied to forked and spawned children and survive
+//! `exec`. The soft limits are enforced, a process may raise one up to its
+//! hard limit and lower the hard limit, there are no privileged processes to
+//! raise it again.
+
+use syscall_def::{Errno, Rlimit, RlimitValue};
+
+/// Ticks a process may run past its CPU limit before it is killed
+pub const CPU_LIMIT_GRACE_TICKS: usize = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceLimits {
+    cpu_ticks: RlimitValue,
+    heap: RlimitValue,
+    stack: RlimitValue,
+    resident_pages: RlimitValue,
+    open_files: RlimitValue,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            cpu_ticks: RlimitValue::INFINITY,
+            heap: RlimitValue::INFINITY,
+            stack: RlimitValue::INFINITY,
+            resident_pages: RlimitValue::INFINITY,
+            open_files: RlimitValue::INFINITY,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// The enforced, soft limit on `res`
+    pub fn get(&self, res: Rlimit) -> usize {
+        self.value(res).soft
+    }
+
+    pub fn value(&self, res: Rlimit) -> RlimitValue {
+        match res {
+            Rlimit::CpuTicks => self.cpu_ticks,
+            Rlimit::Heap => self.heap,
+            Rlimit::Stack => self.stack,
+            Rlimit::ResidentPages => self.resident_pages,
+            Rlimit::OpenFiles => self.open_files,
+        }
+    }
+
+    /// Set the limits on `res`, `PermissionDenied` if that would raise the
+    /// hard limit
+    pub fn set(&mut self, res: Rlimit, value: RlimitValue) -> Result<(), Errno> {
+        let slot = match res {
+            Rlimit::CpuTicks => &mut self.cpu_ticks,
+            Rlimit::Heap => &mut self.heap,
+            Rlimit::Stack => &mut self.stack,
+            Rlimit::ResidentPages => &mut self.resident_pages,
+            Rlimit::OpenFiles => &mut self.open_files,
+        };
+        if value.soft > value.hard {
+            return Err(Errno::InvalidArgument);
+        }
+        if value.hard > slot.hard {
+            return Err(Errno::PermissionDenied);
+        }
+        *slot = value;
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+//! Scheduling policies
+//!
+//! The manager owns one `Scheduler` per CPU, picked at boot by the `scheduler`
+//! key of the boot config. A CPU with an empty queue steals from the others.
+//! A scheduler only orders the pids that are ready to run,
+//! what it needs to know about a process is kept in its `SchedInfo`, so it
+//! follows the process around and shows up in the process list.
+//!
+//! Queues may hold stale pids (processes that died or blocked after being
+//! queued), the manager skips those when it picks the next process.
+
+use super::ProcessId;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+const NICE_LEVELS: usize = (NICE_MAX - NICE_MIN) as usize + 1;
+
+/// Scheduling state of a process
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedInfo {
+    nice: i8,
+    /// feedback queue level, 0 is the most interactive one
+    level: u8,
+    /// ticks run at `level` since the process got there
+    used: u32,
+    /// boost epoch `level` belongs to, 0 before the first scheduling
+    epoch: u32,
+    /// CPU the process last ran on, whose queue it goes back to
+    cpu: Option<u8>,
+    /// whether the process is in a queue, it never is twice
+    queued: bool,
+}
+
+impl SchedInfo {
+    /// State of a new process, only the nice value is inherited
+    pub fn inherit(&self) -> Self {
+        Self {
+            nice: self.nice,
+            ..Self::default()
+        }
+    }
+
+    pub fn nice(&self) -> i8 {
+        self.nice
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub fn cpu(&self) -> Option<usize> {
+        self.cpu.map(usize::from)
+    }
+
+    pub fn set_cpu(&mut self, cpu: usize) {
+        self.cpu = Some(cpu as u8);
+    }
+
+    pub fn is_queued(&self) -> bool {
+        self.queued
+    }
+
+    pub fn set_queued(&mut self, queued: bool) {
+        self.queued = queued;
+    }
+}
+
+pub trait Scheduler: Send + core::fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    /// Queue `pid`, which is ready to run
+    fn push(&mut self, pid: ProcessId, info: &mut SchedInfo);
+
+    /// Take the next pid to run
+    fn pop(&mut self) -> Option<ProcessId>;
+
+    /// Number of queued pids, stale ones included
+    fn queued(&self) -> usize;
+
+    /// Account a timer tick to the running process, true if it should give
+    /// up the CPU now
+    fn tick(&mut self, info: &mut SchedInfo) -> bool;
+
+    /// Change the nice value of a process, clamped to `NICE_MIN..=NICE_MAX`
+    fn set_nice(&mut self, info: &mut SchedInfo, nice: i8) {
+        info.nice = nice.clamp(NICE_MIN, NICE_MAX);
+    }
+}
+
+/// Create the scheduler named by the boot config, round-robin by default
+pub fn from_name(name: &str) -> Box<dyn Scheduler> {
+    match name {
+        "rr" | "" => Box::new(RoundRobin::default()),
+        "priority" => Box::new(StaticPriority::default()),
+        "mlfq" => Box::new(FeedbackQueue::default()),
+        _ => {
+            warn!("Unknown scheduler {}, using round-robin.", name);
+            Box::new(RoundRobin::default())
+        }
+    }
+}
+
+/// Every process runs for one tick in turn, nice values are ignored
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    queue: VecDeque<ProcessId>,
+}
+
+impl Scheduler for RoundRobin {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+
+    fn push(&mut self, pid: ProcessId, _info: &mut SchedInfo) {
+        self.queue.push_back(pid);
+    }
+
+    fn pop(&mut self) -> Option<ProcessId> {
+        self.queue.pop_front()
+    }
+
+    fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn tick(&mut self, _info: &mut SchedInfo) -> bool {
+        true
+    }
+}
+
+/// One queue per nice value, the lowest nice value that has a ready process
+/// always runs, round-robin among equals
+///
+/// processes with a higher nice value starve while a lower one is busy
+pub struct StaticPriority {
+    queues: [VecDeque<ProcessId>; NICE_LEVELS],
+}
+
+impl Default for StaticPriority {
+    fn default() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+        }
+    }
+}
+
+impl core::fmt::Debug for StaticPriority {
+    // only the nice values that have queued processes
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.queues
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, queue)| !queue.is_empty())
+                    .map(|(i, queue)| (i as isize + NICE_MIN as isize, queue)),
+            )
+            .finish()
+    }
+}
+
+impl Scheduler for StaticPriority {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn push(&mut self, pid: ProcessId, info: &mut SchedInfo) {
+        self.queues[(info.nice - NICE_MIN) as usize].push_back(pid);
+    }
+
+    fn pop(&mut self) -> Option<ProcessId> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+
+    fn queued(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    fn tick(&mut self, _info: &mut SchedInfo) -> bool {
+        true
+    }
+}
+
+const MLFQ_LEVELS: usize = 3;
+
+/// Ticks between two boosts of every process back to its base level
+const MLFQ_BOOST_TICKS: u32 = 100;
+
+/// Multi-level feedback queue
+///
+/// a process starts at the level given by its nice value and drops one
+/// level once it used up the time slice of its level, whether in one go or
+/// across several wake ups. Processes that mostly wait, like the shell,
+/// stay on top and preempt the CPU-bound ones as soon as they are ready.
+/// Every `MLFQ_BOOST_TICKS` all processes go back to their base level, so
+/// the bottom level does not starve.
+#[derive(Debug)]
+pub struct FeedbackQueue {
+    queues: [VecDeque<ProcessId>; MLFQ_LEVELS],
+    ticks: u32,
+    epoch: u32,
+}
+
+impl Default for FeedbackQueue {
+    fn default() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            ticks: 0,
+            epoch: 1,
+        }
+    }
+}
+
+impl FeedbackQueue {
+    /// Time slice of `level`, in ticks
+    fn slice(level: u8) -> u32 {
+        1 << level
+    }
+
+    /// Level a process with `nice` starts at, niceness only lowers it
+    fn base_level(nice: i8) -> u8 {
+        (nice.max(0) as usize * MLFQ_LEVELS / (NICE_MAX as usize + 1)) as u8
+    }
+
+    /// Move `info` back to its base level if a boost happened since it was
+    /// last scheduled
+    fn refresh(&self, info: &mut SchedInfo) {
+        if info.epoch != self.epoch {
+            info.level = Self::base_level(info.nice);
+            info.used = 0;
+            info.epoch = self.epoch;
+        }
+    }
+
+    fn boost(&mut self) {
+        self.epoch = self.epoch.wrapping_add(1).max(1);
+        let (top, rest) = self.queues.split_first_mut().unwrap();
+        for queue in rest {
+            top.append(queue);
+        }
+    }
+}
+
+impl Scheduler for FeedbackQueue {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn push(&mut self, pid: ProcessId, info: &mut SchedInfo) {
+        self.refresh(info);
+        self.queues[info.level as usize].push_back(pid);
+    }
+
+    fn pop(&mut self) -> Option<ProcessId> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+
+    fn queued(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    fn tick(&mut self, info: &mut SchedInfo) -> bool {
+        self.ticks = self.ticks.wrapping_add(1);
+        if self.ticks % MLFQ_BOOST_TICKS == 0 {
+            self.boost();
+        }
+        self.refresh(info);
+
+        info.used += 1;
+        if info.used >= Self::slice(info.level) {
+            info.level = core::cmp::min(info.level + 1, MLFQ_LEVELS as u8 - 1);
+            info.used = 0;
+            return true;
+        }
+        // someone more interactive woke up
+        self.queues[..info.level as usize]
+            .iter()
+            .any(|queue| !queue.is_empty())
+    }
+
+    fn set_nice(&mut self, info: &mut SchedInfo, nice: i8) {
+        info.nice = nice.clamp(NICE_MIN, NICE_MAX);
+        info.level = Self::base_level(info.nice);
+        info.used = 0;
+    }
+}
+
@@ -0,0 +1,228 @@
+//! Signal state of a process and delivery to user handlers
+//!
+//! Signals are only acted upon when a process is about to return to user
+//! mode: at the end of a syscall or after a timer switch. A user handler
+//! runs on the user stack of the process, on top of a `SignalFrame` holding
+//! the interrupted context, and returns to a restorer issuing `SigReturn`.
+
+use super::context::ProcessContextValue;
+use super::rlimit::ResourceLimits;
+use super::vm::ProcessVm;
+use super::ProcessContext;
+use core::mem::size_of;
+use syscall_def::signal::{Signal, NSIG, SIG_DFL, SIG_IGN};
+use syscall_def::Errno;
+
+/// What a process does with a signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigAction {
+    Default,
+    Ignore,
+    /// `extern "C" fn(sig: usize)`, returning to `restorer`
+    Handler { handler: u64, restorer: u64 },
+}
+
+impl SigAction {
+    /// Raw handler value as seen by `SigAction`
+    pub fn as_raw(&self) -> usize {
+        match *self {
+            SigAction::Default => SIG_DFL,
+            SigAction::Ignore => SIG_IGN,
+            SigAction::Handler { handler, .. } => handler as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+pub fn default_action(sig: Signal) -> DefaultAction {
+    match sig {
+        Signal::Child => DefaultAction::Ignore,
+        Signal::Continue => DefaultAction::Continue,
+        Signal::Stop | Signal::TerminalStop | Signal::TerminalInput => DefaultAction::Stop,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Exit code of a process terminated by `sig`, as shells report it
+pub fn exit_code(sig: Signal) -> isize {
+    128 + sig as isize
+}
+
+const UNBLOCKABLE: u64 = Signal::Kill.mask() | Signal::Stop.mask();
+
+#[derive(Clone, Debug)]
+pub struct SignalState {
+    pending: u64,
+    blocked: u64,
+    actions: [SigAction; NSIG],
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::Default; NSIG],
+        }
+    }
+}
+
+impl SignalState {
+    /// State of a forked child, pending signals are not inherited
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..self.clone()
+        }
+    }
+
+    /// Handlers point into the old image, reset them, ignored signals stay
+    /// ignored
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let SigAction::Handler { .. } = action {
+                *action = SigAction::Default;
+            }
+        }
+    }
+
+    pub fn action(&self, sig: Signal) -> SigAction {
+        self.actions[sig as usize]
+    }
+
+    /// Install `action` for `sig`, returns the previous one
+    pub fn set_action(&mut self, sig: Signal, action: SigAction) -> Result<SigAction, Errno> {
+        if !sig.catchable() {
+            return Err(Errno::InvalidArgument);
+        }
+        Ok(core::mem::replace(&mut self.actions[sig as usize], action))
+    }
+
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, mask: u64) {
+        self.blocked = mask & !UNBLOCKABLE;
+    }
+
+    pub fn is_blocked(&self, sig: Signal) -> bool {
+        self.blocked & sig.mask() != 0
+    }
+
+    /// Whether `sig` would be discarded on delivery anyway
+    pub fn is_ignored(&self, sig: Signal) -> bool {
+        match self.action(sig) {
+            SigAction::Ignore => true,
+            SigAction::Default => default_action(sig) == DefaultAction::Ignore,
+            SigAction::Handler { .. } => false,
+        }
+    }
+
+    pub fn post(&mut self, sig: Signal) {
+        self.pending |= sig.mask();
+    }
+
+    pub fn clear(&mut self, sig: Signal) {
+        self.pending &= !sig.mask();
+    }
+
+    /// Take the lowest pending signal that is not blocked
+    pub fn take_pending(&mut self) -> Option<Signal> {
+        let ready = self.pending & !self.blocked;
+        if ready == 0 {
+            return None;
+        }
+        let sig = ready.trailing_zeros() as usize;
+        self.pending &= !(1 << sig);
+        Signal::try_from(sig).ok()
+    }
+}
+
+/// Saved on the user stack while a handler runs, restored by `SigReturn`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    context: ProcessContextValue,
+    blocked: u64,
+}
+
+/// Bytes below the interrupted `rsp` the code may still use
+const RED_ZONE: u64 = 128;
+
+/// Make `context` enter `handler` for `sig`, the handler returns to `restorer`
+///
+/// `sig` stays blocked until the handler returns, false if the frame could
+/// not be written to the user stack
+pub fn enter_handler(
+    vm: &mut ProcessVm,
+    limits: &ResourceLimits,
+    state: &mut SignalState,
+    sig: Signal,
+    handler: u64,
+    restorer: u64,
+    context: &mut ProcessContext,
+) -> bool {
+    let frame = SignalFrame {
+        context: **context,
+        blocked: state.blocked,
+    };
+    let frame_addr = match context
+        .stack_top()
+        .checked_sub(RED_ZONE + size_of::<SignalFrame>() as u64)
+    {
+        Some(addr) => addr & !0xf,
+        None => return false,
+    };
+    // the handler is entered as if `restorer` had called it
+    let stack_top = frame_addr - 8;
+
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            &frame as *const SignalFrame as *const u8,
+            size_of::<SignalFrame>(),
+        )
+    };
+    if !vm.write_user(frame_addr, bytes, limits)
+        || !vm.write_user(stack_top, &restorer.to_ne_bytes(), limits)
+    {
+        return false;
+    }
+
+    state.blocked |= sig.mask();
+    context.enter_signal_handler(handler, stack_top, sig as usize);
+    true
+}
+
+/// Restore the context saved by `enter_handler`
+///
+/// the restorer issues `SigReturn` right after the handler returned, so the
+/// frame is at the user `rsp`, false if it can not be read or is invalid
+pub fn leave_handler(
+    vm: &mut ProcessVm,
+    limits: &ResourceLimits,
+    state: &mut SignalState,
+    context: &mut ProcessContext,
+) -> bool {
+    let mut frame = core::mem::MaybeUninit::<SignalFrame>::uninit();
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(frame.as_mut_ptr() as *mut u8, size_of::<SignalFrame>())
+    };
+    if !vm.read_user(context.stack_top(), bytes, limits) {
+        return false;
+    }
+
+    let frame = unsafe { frame.assume_init() };
+    if !context.restore_user(&frame.context) {
+        return false;
+    }
+    state.set_blocked(frame.blocked);
+    true
+}
@@ -0,0 +1,71 @@
+//! Validated access to the memory of the current user process
+//!
+//! Syscall handlers must never build slices from user addresses directly,
+//! every buffer goes through these helpers, which check the range against
+//! the caller's `ProcessVm` before copying.
+
+use super::manager::get_process_manager;
+use super::rlimit::ResourceLimits;
+use super::vm::ProcessVm;
+use alloc::{string::String, vec};
+use syscall_def::Errno;
+
+/// Longest path or string a syscall copies from user space
+pub const PATH_MAX: usize = 4096;
+
+/// Bytes copied at a time by syscalls whose buffer length is up to the user
+pub const USER_COPY_CHUNK: usize = 4096;
+
+/// Run `f` on the address space of the current process
+///
+/// pages copied on write meanwhile are shot down once the process is
+/// unlocked, other threads may still read the frames they were copied from
+fn with_vm<T>(f: impl FnOnce(&mut ProcessVm, &ResourceLimits) -> T) -> T {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let mut inner = proc.write();
+        let limits = inner.limits();
+        let vm = inner.vm_mut();
+        let ret = f(vm, &limits);
+        let remapped = vm.take_remapped();
+        drop(inner);
+        if remapped {
+            crate::interrupt::tlb::shootdown();
+        }
+        ret
+    })
+}
+
+/// Copy `buf.len()` bytes from user address `src` into `buf`
+pub fn copy_from_user(src: usize, buf: &mut [u8]) -> Result<(), Errno> {
+    with_vm(|vm, limits| vm.read_user(src as u64, buf, limits))
+        .then_some(())
+        .ok_or(Errno::BadAddress)
+}
+
+/// Copy `buf` to user address `dst`
+pub fn copy_to_user(dst: usize, buf: &[u8]) -> Result<(), Errno> {
+    with_vm(|vm, limits| vm.write_user(dst as u64, buf, limits))
+        .then_some(())
+        .ok_or(Errno::BadAddress)
+}
+
+/// Check that `[addr, addr + len)` can be accessed by the current process
+pub fn access_ok(addr: usize, len: usize, write: bool) -> bool {
+    with_vm(|vm, limits| vm.check_user_range(addr as u64, len, write, limits))
+}
+
+/// Copy an utf-8 string of `len` bytes from user address `ptr`, at most
+/// `PATH_MAX`
+pub fn user_str(ptr: usize, len: usize) -> Result<String, Errno> {
+    if len > PATH_MAX {
+        return Err(Errno::NameTooLong);
+    }
+    if !access_ok(ptr, len, false) {
+        return Err(Errno::BadAddress);
+    }
+
+    let mut buf = vec![0u8; len];
+    copy_from_user(ptr, &mut buf)?;
+    String::from_utf8(buf).map_err(|_| Errno::InvalidArgument)
+}
@@ -41,16 +41,27 @@ impl Heap {
         }
     }
 
-    pub fn fork(&self) -> Self {
+    /// The same heap, for another thread of the process
+    pub fn share(&self) -> Self {
         Self {
             base: self.base,
             end: self.end.clone(),
         }
     }
 
+    /// The heap of a forked child, which has its own copy of the pages
+    pub fn fork(&self) -> Self {
+        Self {
+            base: self.base,
+            end: Arc::new(AtomicU64::new(self.end.load(Ordering::Relaxed))),
+        }
+    }
+
+    /// Move the end of the heap, keeping the heap within `max_size` bytes
     pub fn brk(
         &self,
         new_end: Option<VirtAddr>,
+        max_size: u64,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
     ) -> Option<VirtAddr> {
@@ -61,6 +72,10 @@ impl Heap {
         if new_end.as_u64() < HEAP_START || new_end.as_u64() > HEAP_END {
             return None;
         }
+        if new_end.as_u64() - HEAP_START > max_size {
+            debug!("Heap limit of {:#x} bytes reached.", max_size);
+            return None;
+        }
         let user_access = processor::get_pid() != KERNEL_PID;
         let current_end = self.end.load(Ordering::Relaxed);
         let diff = new_end.as_u64() as i64 - current_end as i64;
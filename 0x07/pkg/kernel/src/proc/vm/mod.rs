@@ -1,12 +1,13 @@
-use alloc::{format, string::String};
+use alloc::{format, string::String, vec};
 use boot::VirtualAddress;
 use stack::{STACK_DEF_PAGE, STACK_INIT_BOT, STACK_MAX, STACK_MAX_PAGES};
 use x86_64::{
-    structures::paging::{page::*, *},
-    VirtAddr,
+    structures::paging::{mapper::{MappedFrame, TranslateResult}, page::*, *},
+    PhysAddr, VirtAddr,
 };
+use core::ptr::copy_nonoverlapping;
 use crate::proc::*;
-use crate::proc::vm::mapper::UnmapError;
+use crate::proc::vm::mapper::{MapToError, UnmapError};
 use crate::alloc::borrow::ToOwned;
 use boot::KernelPages;
 use crate::proc::vm::stack::STACK_MAX_SIZE;
@@ -22,35 +23,75 @@ use self::stack::Stack;
 pub mod heap;
 use crate::proc::vm::heap::Heap;
 use super::{manager::{self, ProcessManager}, PageTableContext, ProcessId};
+use super::paging::COPY_ON_WRITE;
+use super::rlimit::ResourceLimits;
+use syscall_def::Rlimit;
 use x86_64::structures::paging::mapper::CleanUp;
 type MapperRef<'a> = &'a mut OffsetPageTable<'static>;
 type FrameAllocatorRef<'a> = &'a mut BootInfoFrameAllocator;
 
+// the canonical lower half, user pointers must stay below it
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+// arguments and environment must fit in the first page of the initial stack
+pub const ARG_MAX: usize = 2048;
+
+/// A mapped region of an address space, see `ProcessVm::regions`
+pub struct Region {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub writable: bool,
+    pub executable: bool,
+    pub name: &'static str,
+}
+
+/// Where `ProcessVm::init_args` put the arguments of a new image
+pub struct InitArgs {
+    pub stack_top: VirtAddr,
+    pub argc: usize,
+    pub argv: u64,
+    pub envc: usize,
+    pub envp: u64,
+}
+
 pub struct ProcessVm {
-    // page table is shared by parent and child
     pub(super) page_table: PageTableContext,
     pub(super) heap: Heap,
     // stack is pre-process allocated
     pub(super) stack: Stack,
     pub(super) code: Vec<PageRangeInclusive>,
     pub(super) code_usage: u64,
+    // a copy-on-write page was given a frame of its own, other CPUs may
+    // still map the old one, see `take_remapped`
+    remapped: bool,
 }
 
 impl ProcessVm {
 
-    pub fn fork(&self, stack_offset_count: u64) -> Self {
-        // clone the page table context (see instructions)
-        let owned_page_table = self.page_table.fork();
-
-        let mapper = &mut owned_page_table.mapper();
-        let alloc = &mut *get_frame_alloc_for_sure();
+    /// The address space seen by another thread, which brings its own stack
+    ///
+    /// the code, heap and page table are released by the last thread
+    pub fn share(&self) -> Self {
+        Self {
+            page_table: self.page_table.share(),
+            heap: self.heap.share(),
+            stack: Stack::empty(),
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+            remapped: false,
+        }
+    }
 
+    /// Copy-on-write duplicate for a forked child, everything stays at the
+    /// same addresses, see `PageTableContext::fork`
+    pub fn fork(&self, kernel: &PageTableContext) -> Self {
         Self {
-            page_table: owned_page_table,
+            page_table: self.page_table.fork(kernel),
             heap: self.heap.fork(),
-            stack: self.stack.fork(mapper, alloc, stack_offset_count),
-            code :Vec::new(),
-            code_usage: 0,
+            stack: self.stack.fork(),
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+            remapped: false,
         }
     }
     pub fn new(page_table: PageTableContext) -> Self {
@@ -60,6 +101,7 @@ impl ProcessVm {
             stack: Stack::empty(),
             code: Vec::new(),
             code_usage: 0,
+            remapped: false,
         }
     }
 
@@ -88,30 +130,305 @@ impl ProcessVm {
         VirtAddr::new(addr)
     }
 
-    pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
+    pub fn handle_page_fault(&mut self, addr: VirtAddr, limits: &ResourceLimits) -> bool {
+        if self.copy_on_write(addr) {
+            return true;
+        }
+
+        // the stack may take what is left of the resident pages
+        let stack_pages = self.stack.memory_usage() / PAGE_SIZE;
+        let room = (limits.get(Rlimit::ResidentPages) as u64).saturating_sub(self.resident_pages());
+        let max_pages = (limits.get(Rlimit::Stack) as u64 / PAGE_SIZE).min(stack_pages.saturating_add(room));
+
         let mapper = &mut self.page_table.mapper();
         let alloc = &mut *get_frame_alloc_for_sure();
 
-        self.stack.handle_page_fault(addr, mapper, alloc)
+        self.stack.handle_page_fault(addr, max_pages, mapper, alloc)
+    }
+
+    /// Make the copy-on-write page at `addr` writable, copying it unless
+    /// this address space is the last one using the frame
+    ///
+    /// true if the page is writable afterwards, also if it already was:
+    /// another thread copied it first and the fault came from a stale TLB
+    /// entry. The threads share the page table, the frame allocator lock
+    /// keeps them from copying the same page at once.
+    fn copy_on_write(&mut self, addr: VirtAddr) -> bool {
+        let alloc = &mut *get_frame_alloc_for_sure();
+        let mut mapper = self.page_table.mapper();
+        let (frame, flags) = match mapper.translate(addr) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } => (frame, flags),
+            _ => return false,
+        };
+        let page = Page::<Size4KiB>::containing_address(addr);
+        if !flags.contains(COPY_ON_WRITE) {
+            let writable = flags.contains(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE);
+            if writable {
+                x86_64::instructions::tlb::flush(page.start_address());
+            }
+            return writable;
+        }
+        let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+
+        if alloc.ref_count(frame) == 1 {
+            return match unsafe { mapper.update_flags(page, flags) } {
+                Ok(flush) => {
+                    flush.flush();
+                    true
+                }
+                Err(_) => false,
+            };
+        }
+
+        let new_frame = match alloc.allocate_frame() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        unsafe {
+            copy_nonoverlapping(
+                physical_to_virtual(frame.start_address().as_u64()) as *const u8,
+                physical_to_virtual(new_frame.start_address().as_u64()) as *mut u8,
+                PAGE_SIZE as usize,
+            );
+        }
+        match mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                unsafe { alloc.deallocate_frame(frame) };
+            }
+            Err(_) => return false,
+        }
+        self.remapped = true;
+        match unsafe { mapper.map_to(page, new_frame, flags, alloc) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Whether a copy-on-write page was copied since the last call
+    ///
+    /// other threads may still read the old frame through their TLB, the
+    /// caller shoots it down with `tlb::shootdown` once it holds no locks
+    pub fn take_remapped(&mut self) -> bool {
+        core::mem::take(&mut self.remapped)
+    }
+
+    /// Translate a user address to the physical address behind it
+    ///
+    /// the page must be mapped with user access (and write access if `write`),
+    /// stack pages that are not mapped yet are grown on demand and
+    /// copy-on-write pages are copied before being written
+    fn translate_user(
+        &mut self,
+        addr: VirtAddr,
+        write: bool,
+        limits: &ResourceLimits,
+    ) -> Option<PhysAddr> {
+        for _ in 0..2 {
+            match self.page_table.mapper().translate(addr) {
+                TranslateResult::Mapped { frame, offset, flags } => {
+                    if !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+                        return None;
+                    }
+                    if write && !flags.contains(PageTableFlags::WRITABLE) {
+                        // the copy is writable, translate it again
+                        if !self.copy_on_write(addr) {
+                            return None;
+                        }
+                        continue;
+                    }
+                    return Some(frame.start_address() + offset);
+                }
+                _ => {
+                    if !self.handle_page_fault(addr, limits) {
+                        return None;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Check that `[addr, addr + len)` lies in the user half and is accessible
+    /// by the process, see `translate_user`
+    pub fn check_user_range(
+        &mut self,
+        addr: u64,
+        len: usize,
+        write: bool,
+        limits: &ResourceLimits,
+    ) -> bool {
+        if len == 0 {
+            return true;
+        }
+        let end = match addr.checked_add(len as u64) {
+            Some(end) if end <= USER_SPACE_END => end,
+            _ => return false,
+        };
+
+        let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        let end_page = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+        Page::range_inclusive(start_page, end_page)
+            .all(|page| self.translate_user(page.start_address(), write, limits).is_some())
+    }
+
+    /// Copy bytes between the user range `[addr, addr + len)` and the kernel
+    ///
+    /// the whole range is checked before anything is copied, the copy goes
+    /// through the physical memory mapping, so the address space does not
+    /// need to be the active one
+    fn copy_user(
+        &mut self,
+        addr: u64,
+        len: usize,
+        write: bool,
+        limits: &ResourceLimits,
+        mut copy: impl FnMut(*mut u8, usize, usize),
+    ) -> bool {
+        if !self.check_user_range(addr, len, write, limits) {
+            return false;
+        }
+
+        let mut copied = 0;
+        while copied < len {
+            let cur = VirtAddr::new(addr + copied as u64);
+            let count = core::cmp::min(
+                len - copied,
+                (PAGE_SIZE - u64::from(cur.page_offset())) as usize,
+            );
+            let phys = match self.translate_user(cur, write, limits) {
+                Some(phys) => phys,
+                None => return false,
+            };
+            copy(physical_to_virtual(phys.as_u64()) as *mut u8, copied, count);
+            copied += count;
+        }
+        true
+    }
+
+    /// Copy `buf.len()` bytes from user address `addr` into `buf`
+    pub fn read_user(&mut self, addr: u64, buf: &mut [u8], limits: &ResourceLimits) -> bool {
+        let dst = buf.as_mut_ptr();
+        self.copy_user(addr, buf.len(), false, limits, |src, offset, count| unsafe {
+            copy_nonoverlapping(src as *const u8, dst.add(offset), count);
+        })
+    }
+
+    /// Copy `buf` to user address `addr`
+    pub fn write_user(&mut self, addr: u64, buf: &[u8], limits: &ResourceLimits) -> bool {
+        let src = buf.as_ptr();
+        self.copy_user(addr, buf.len(), true, limits, |dst, offset, count| unsafe {
+            copy_nonoverlapping(src.add(offset), dst, count);
+        })
+    }
+
+    /// Lay out `argv` and `envp` at the top of the initial stack
+    ///
+    /// the strings go right below `STACK_INIT_TOP`, under them two arrays of
+    /// `(ptr, len)` pairs describe the arguments and the environment, the
+    /// returned stack top is just below the arrays
+    pub fn init_args(
+        &mut self,
+        argv: &[&str],
+        envp: &[&str],
+        limits: &ResourceLimits,
+    ) -> Result<InitArgs, Errno> {
+        let count = argv.len() + envp.len();
+        let size = argv.iter().chain(envp).map(|s| s.len()).sum::<usize>() + count * 16;
+        if size > ARG_MAX {
+            return Err(Errno::ArgumentListTooLong);
+        }
+
+        let base = (STACK_INIT_TOP - size as u64) & !0xf;
+        let mut image = vec![0u8; (STACK_INIT_TOP - base) as usize];
+        let mut offset = count * 16;
+        for (i, s) in argv.iter().chain(envp).enumerate() {
+            let pair = &mut image[i * 16..(i + 1) * 16];
+            pair[..8].copy_from_slice(&(base + offset as u64).to_ne_bytes());
+            pair[8..].copy_from_slice(&(s.len() as u64).to_ne_bytes());
+            image[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+            offset += s.len();
+        }
+
+        if !self.write_user(base, &image, limits) {
+            return Err(Errno::NoMemory);
+        }
+
+        Ok(InitArgs {
+            // as if the entry had been called, keeping the usual alignment
+            stack_top: VirtAddr::new(base - 8),
+            argc: argv.len(),
+            argv: base,
+            envc: envp.len(),
+            envp: base + argv.len() as u64 * 16,
+        })
     }
 
     pub(super) fn memory_usage(&self) -> u64 {
         self.stack.memory_usage()
     }
-    pub fn load_elf(&mut self, elf: &ElfFile) {
+
+    /// Pages mapped for the code, stack and heap, shared ones included
+    pub fn resident_pages(&self) -> u64 {
+        self.code_usage
+            + self.stack.memory_usage() / PAGE_SIZE
+            + self.heap.memory_usage().div_ceil(PAGE_SIZE)
+    }
+
+    /// Code segments, heap and stack, in address order
+    ///
+    /// permissions are those of the first page, copy-on-write pages count
+    /// as writable
+    pub fn regions(&self) -> Vec<Region> {
+        let mapper = self.page_table.mapper();
+        let region = |start: VirtAddr, end: VirtAddr, name| {
+            let flags = match mapper.translate(start) {
+                TranslateResult::Mapped { flags, .. } => flags,
+                _ => PageTableFlags::empty(),
+            };
+            Region {
+                start,
+                end,
+                writable: flags.intersects(PageTableFlags::WRITABLE | COPY_ON_WRITE),
+                executable: !flags.contains(PageTableFlags::NO_EXECUTE),
+                name,
+            }
+        };
+
+        let mut regions: Vec<Region> = self
+            .code
+            .iter()
+            .map(|range| region(range.start.start_address(), range.end.start_address() + PAGE_SIZE, "code"))
+            .collect();
+        let heap_size = self.heap.memory_usage();
+        if heap_size > 0 {
+            let start = VirtAddr::new(heap::HEAP_START);
+            regions.push(region(start, start + heap_size, "heap"));
+        }
+        if self.stack.memory_usage() > 0 {
+            let range = self.stack.range;
+            regions.push(region(range.start.start_address(), range.end.start_address(), "stack"));
+        }
+        regions.sort_by_key(|region| region.start);
+        regions
+    }
+
+    pub fn load_elf(&mut self, elf: &ElfFile) -> Result<(), MapToError<Size4KiB>> {
         let mapper = &mut self.page_table.mapper();
         let alloc = &mut *get_frame_alloc_for_sure();
 
         
         self.stack.init(mapper, alloc);
         // FIXME: load elf to process pagetable
-        elf::load_elf(elf, PHYSICAL_OFFSET.get().cloned().unwrap(), mapper, alloc,true).unwrap();
-        
-    }
-    pub fn unmap(&mut self)-> Result<(), UnmapError>{
-        let mapper = &mut self.page_table.mapper();
-        let alloc = &mut *get_frame_alloc_for_sure();
-        self.stack.unmap(mapper, alloc)?;
+        self.code = elf::load_elf(elf, PHYSICAL_OFFSET.get().cloned().unwrap(), mapper, alloc,true)?;
+        self.code_usage = self.code.iter().map(|range| range.count() as u64).sum();
         Ok(())
     }
     pub fn stack_start(&self) -> VirtAddr {
@@ -125,10 +442,9 @@ impl ProcessVm {
         // NOTE: you may need to implement `frames_recycled` by yourself
         let start_count = dealloc.frames_recycled();
 
-        // TODO...
-        self.stack.clean_up(mapper, dealloc);
+        self.stack.clean_up(mapper, dealloc)?;
         if self.page_table.using_count() == 1{
-            self.heap.clean_up(mapper, dealloc);
+            self.heap.clean_up(mapper, dealloc)?;
             for page_range in self.code.iter() {
                 let start_addr = page_range.start.start_address().as_u64();
                 let page_count = page_range.count() as u64;
@@ -156,8 +472,18 @@ impl ProcessVm {
 
         Ok(())
     }
-    pub fn brk(&self, addr: Option<VirtAddr>) -> Option<VirtAddr> {
-        self.heap.brk(addr,&mut self.page_table.mapper(),&mut get_frame_alloc_for_sure())
+    pub fn brk(&self, addr: Option<VirtAddr>, limits: &ResourceLimits) -> Option<VirtAddr> {
+        if let Some(new_end) = addr {
+            let heap_pages = self.heap.memory_usage().div_ceil(PAGE_SIZE);
+            let new_pages = new_end.as_u64().saturating_sub(heap::HEAP_START).div_ceil(PAGE_SIZE);
+            let resident = self.resident_pages() - heap_pages + new_pages;
+            if new_pages > heap_pages && resident > limits.get(Rlimit::ResidentPages) as u64 {
+                debug!("Resident limit reached, {} pages wanted.", resident);
+                return None;
+            }
+        }
+        let max_size = limits.get(Rlimit::Heap) as u64;
+        self.heap.brk(addr, max_size, &mut self.page_table.mapper(), &mut get_frame_alloc_for_sure())
     }
 }
 
@@ -174,7 +500,7 @@ impl core::fmt::Debug for ProcessVm {
 }
 impl Drop for ProcessVm {
     fn drop(&mut self) {
-        if let Err(err) = self.unmap() {
+        if let Err(err) = self.clean_up() {
             error!("Failed to clean up process memory: {:?}", err);
         }
     }
@@ -5,7 +5,6 @@ use x86_64::{
 use crate::proc::*;
 use x86_64::structures::paging::mapper::UnmapError;
 use super::{FrameAllocatorRef, MapperRef};
-use core::ptr::copy_nonoverlapping;
 // 0xffff_ff00_0000_0000 is the kernel's address space
 pub const STACK_MAX: u64 = 0x4000_0000_0000;
 pub const STACK_MAX_PAGES: u64 = 0x100000;
@@ -43,56 +42,14 @@ pub struct Stack {
 
 impl Stack {
 
-    pub fn fork(
-        &self,
-        mapper: MapperRef,
-        alloc: FrameAllocatorRef,
-        stack_offset_count: u64,
-    ) -> Self {
-        // FIXME: alloc & map new stack for child (see instructions)
-        let mut child_stack_top = (self.range.start - stack_offset_count).start_address();
-        let child_stack_count = self.usage;
-        // FIXME: copy the *entire stack* from parent to child
-        while elf::map_range(child_stack_top.as_u64(),
-                            child_stack_count,
-                            mapper,
-                            alloc,
-                            true
-                            ).is_err()
-        {
-            trace!("Map thread stack to {:#x} failed.", child_stack_top);
-            child_stack_top -= STACK_MAX_SIZE; // stack grow down
-        }
-
-        // FIXME: return the new stack
-        self.clone_range(self.range.start.start_address().as_u64(),
-                child_stack_top.as_u64(), 
-                        child_stack_count
-                        );
-        let child_start = Page::containing_address(child_stack_top);
-        let child_end = Page::containing_address(child_stack_top)+child_stack_count;
-        let child_range = Page::range(child_start,child_end);
+    /// The same stack in the copied address space of a forked child
+    pub fn fork(&self) -> Self {
         Self {
-            range:child_range,
-            usage: child_stack_count
-        }
-    }
-    
-    /// Clone a range of memory
-    ///
-    /// - `src_addr`: the address of the source memory
-    /// - `dest_addr`: the address of the target memory
-    /// - `size`: the count of pages to be cloned
-    fn clone_range(&self, cur_addr: u64, dest_addr: u64, size: u64) {
-        trace!("Clone range: {:#x} -> {:#x}", cur_addr, dest_addr);
-        unsafe {
-            copy_nonoverlapping::<u64>(
-                cur_addr as *mut u64,
-                dest_addr as *mut u64,
-                (size * Size4KiB::SIZE / 8) as usize,
-            );
+            range: self.range,
+            usage: self.usage,
         }
     }
+
     pub fn new(top: Page, size: u64) -> Self {
         Self {
             range: Page::range(top - size + 1, top + 1),
@@ -121,9 +78,11 @@ impl Stack {
         self.usage = STACK_DEF_PAGE;
     }
 
+    /// Grow the stack down to `addr`, to at most `max_pages` pages
     pub fn handle_page_fault(
         &mut self,
         addr: VirtAddr,
+        max_pages: u64,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
     ) -> bool {
@@ -131,7 +90,7 @@ impl Stack {
             return false;
         }
 
-        if let Err(m) = self.grow_stack(addr, mapper, alloc) {
+        if let Err(m) = self.grow_stack(addr, max_pages, mapper, alloc) {
             error!("Grow stack failed: {:?}", m);
             return false;
         }
@@ -152,6 +111,7 @@ impl Stack {
     fn grow_stack(
         &mut self,
         addr: VirtAddr,
+        max_pages: u64,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
     ) -> Result<(), MapToError<Size4KiB>> {
@@ -162,6 +122,10 @@ impl Stack {
         let start_page = self.range.start;
         let alloc_page_nums = start_page - addr_at_page;
         let original_page_size = self.range.end - start_page;
+        if original_page_size + alloc_page_nums > max_pages {
+            debug!("Stack limit of {} pages reached.", max_pages);
+            return Err(MapToError::FrameAllocationFailed);
+        }
 
         let is_user_access = processor::get_pid() != KERNEL_PID;
         elf::map_range(
@@ -201,12 +165,14 @@ impl Stack {
         dealloc: FrameAllocatorRef,
     ) -> Result<(), UnmapError> {
         if self.usage == 0 {
-            warn!("Stack is empty, no need to clean up.");
+            // e.g. threads, which run on stacks their process allocated
+            trace!("Stack is empty, no need to clean up.");
             return Ok(());
         }
 
         // FIXME: unmap stack pages with `elf::unmap_pages`
-        let deallocate_flag = processor::get_pid() != KERNEL_PID;
+        // frames of the kernel stack come from the bootloader
+        let deallocate_flag = !(KSTACK_DEF_BOT..KSTACK_MAX).contains(&self.range.start.start_address().as_u64());
         let start_addr = self.range.start.start_address().as_u64();
         let range_start = Page::containing_address(VirtAddr::new(start_addr));
         let range_end = range_start + self.usage;
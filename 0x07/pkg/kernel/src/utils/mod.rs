@@ -59,8 +59,9 @@ pub fn wait(pid: ProcessId) {
     
         //  let status = proc_manager.get_proc(&pid).unwrap().read().status();
         // HINT: it's better to use the exit code
-        let exit_code = proc_manager.get_proc(&pid).unwrap().read().exit_code();
-        if exit_code.is_none() {
+        // reaped processes are gone from the list
+        let exit_code = proc_manager.get_proc(&pid).map(|proc| proc.read().exit_code());
+        if let Some(None) = exit_code {
             x86_64::instructions::hlt();
         } else {
             break;
@@ -89,3 +89,56 @@ macro_rules! as_handler {
         }
     };
 }
+
+/// `as_handler!` for exceptions that push an error code, which is passed to
+/// `$fn` as its second argument
+///
+/// the error code is swapped with `rbp`, so the registers and the stack frame
+/// still form a `ProcessContext`
+#[macro_export]
+macro_rules! as_handler_with_err {
+    ($fn: ident, $err: ty) => {
+        paste::item! {
+            #[naked]
+            pub extern "x86-interrupt" fn [<$fn _handler>](_sf: InterruptStackFrame, _err: $err) {
+                unsafe {
+                    core::arch::naked_asm!("
+                    xchg rbp, [rsp]
+                    push rax
+                    push rbx
+                    push rcx
+                    push rdx
+                    push rsi
+                    push rdi
+                    push r8
+                    push r9
+                    push r10
+                    push r11
+                    push r12
+                    push r13
+                    push r14
+                    push r15
+                    mov rdi, rbp
+                    call {}
+                    pop r15
+                    pop r14
+                    pop r13
+                    pop r12
+                    pop r11
+                    pop r10
+                    pop r9
+                    pop r8
+                    pop rdi
+                    pop rsi
+                    pop rdx
+                    pop rcx
+                    pop rbx
+                    pop rax
+                    pop rbp
+                    iretq",
+                    sym $fn);
+                }
+            }
+        }
+    };
+}
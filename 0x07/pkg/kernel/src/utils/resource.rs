@@ -3,6 +3,7 @@ use alloc::collections::BTreeMap;
 use storage::FileHandle;
 use spin::Mutex;
 use crate::drivers::input::try_pop_key;
+use syscall_def::{Errno, RLIM_INFINITY};
 #[derive(Debug, Clone)]
 pub enum StdIO {
     Stdin,
@@ -21,39 +22,50 @@ impl Default for ResourceSet {
             handles: BTreeMap::new(),
         };
 
-        res.open(Resource::Console(StdIO::Stdin));
-        res.open(Resource::Console(StdIO::Stdout));
-        res.open(Resource::Console(StdIO::Stderr));
+        for stdio in [StdIO::Stdin, StdIO::Stdout, StdIO::Stderr] {
+            res.open(Resource::Console(stdio), RLIM_INFINITY).unwrap();
+        }
 
         res
     }
 }
 
 impl ResourceSet {
-    pub fn open(&mut self, res: Resource) -> u8 {
-        let fd = self.handles.len() as u8;
+    /// Install `res` at the lowest free fd, unless `max_files` are open
+    pub fn open(&mut self, res: Resource, max_files: usize) -> Result<u8, Errno> {
+        if self.handles.len() >= max_files {
+            return Err(Errno::TooManyFiles);
+        }
+        let fd = (0..=u8::MAX)
+            .find(|fd| !self.handles.contains_key(fd))
+            .ok_or(Errno::TooManyFiles)?;
         self.handles.insert(fd, Mutex::new(res));
-        fd
+        Ok(fd)
     }
 
-    pub fn close(&mut self, fd: u8) -> bool {
-        self.handles.remove(&fd).is_some()
+    pub fn close(&mut self, fd: u8) -> Result<(), Errno> {
+        self.handles.remove(&fd).map(|_| ()).ok_or(Errno::BadFd)
     }
 
-    pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().read(buf)) {
-            count as isize
-        } else {
-            -1
-        }
+    /// Whether `fd` reads from the console
+    pub fn is_console_input(&self, fd: u8) -> bool {
+        self.handles
+            .get(&fd)
+            .is_some_and(|h| matches!(*h.lock(), Resource::Console(StdIO::Stdin)))
     }
 
-    pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().write(buf)) {
-            count as isize
-        } else {
-            -1
-        }
+    pub fn read(&self, fd: u8, buf: &mut [u8]) -> Result<usize, Errno> {
+        self.handles
+            .get(&fd)
+            .and_then(|h| h.lock().read(buf))
+            .ok_or(Errno::BadFd)
+    }
+
+    pub fn write(&self, fd: u8, buf: &[u8]) -> Result<usize, Errno> {
+        self.handles
+            .get(&fd)
+            .and_then(|h| h.lock().write(buf))
+            .ok_or(Errno::BadFd)
     }
 }
 
@@ -108,3 +120,15 @@ impl Resource {
         }
     }
 }
+
+impl core::fmt::Display for Resource {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Resource::Console(StdIO::Stdin) => write!(f, "console:stdin"),
+            Resource::Console(StdIO::Stdout) => write!(f, "console:stdout"),
+            Resource::Console(StdIO::Stderr) => write!(f, "console:stderr"),
+            Resource::File(file) => write!(f, "file:{}", file.meta.name),
+            Resource::Null => write!(f, "null"),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Arguments and environment of the current process
+//!
+//! The kernel lays both out on the initial stack as arrays of `(ptr, len)`
+//! pairs and passes them to `_start`, where `entry!` records them here.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicUsize = AtomicUsize::new(0);
+static ENVC: AtomicUsize = AtomicUsize::new(0);
+static ENVP: AtomicUsize = AtomicUsize::new(0);
+
+#[doc(hidden)]
+pub fn init_args(argc: usize, argv: usize, envc: usize, envp: usize) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv, Ordering::Relaxed);
+    ENVC.store(envc, Ordering::Relaxed);
+    ENVP.store(envp, Ordering::Relaxed);
+}
+
+fn strings(count: usize, addr: usize) -> impl Iterator<Item = &'static str> {
+    let pairs: &'static [[usize; 2]] = if count == 0 || addr == 0 {
+        &[]
+    } else {
+        unsafe { core::slice::from_raw_parts(addr as *const [usize; 2], count) }
+    };
+
+    pairs.iter().map(|&[ptr, len]| unsafe {
+        core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr as *const u8, len))
+    })
+}
+
+/// Arguments of the process, the first one is the program path
+pub fn args() -> impl Iterator<Item = &'static str> {
+    strings(ARGC.load(Ordering::Relaxed), ARGV.load(Ordering::Relaxed))
+}
+
+/// Environment the process started with as `(key, value)` pairs
+pub fn env() -> impl Iterator<Item = (&'static str, &'static str)> {
+    strings(ENVC.load(Ordering::Relaxed), ENVP.load(Ordering::Relaxed))
+        .map(|var| var.split_once('=').unwrap_or((var, "")))
+}
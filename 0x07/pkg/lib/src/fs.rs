@@ -0,0 +1,54 @@
+//! Directory listings, parsed from the records `GetDents` writes
+
+use crate::sys_get_dents;
+use alloc::{string::String, vec, vec::Vec};
+use syscall_def::{Dirent, Errno, TIME_UNKNOWN};
+
+/// Buffer for one `GetDents` call, larger entries fail with `InvalidArgument`
+const DENTS_BUF_SIZE: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    /// in bytes, 0 for directories
+    pub size: u64,
+    /// seconds since the Unix epoch, `None` if the file system does not
+    /// keep them
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+}
+
+fn time(secs: i64) -> Option<i64> {
+    (secs != TIME_UNKNOWN).then_some(secs)
+}
+
+/// All entries of the directory `path`, in the order of the file system
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, Errno> {
+    let mut entries = Vec::new();
+    let mut buf = vec![0u8; DENTS_BUF_SIZE];
+    loop {
+        let len = sys_get_dents(path, &mut buf, entries.len())?;
+        if len == 0 {
+            return Ok(entries);
+        }
+
+        let mut offset = 0;
+        while offset < len {
+            let dirent =
+                unsafe { core::ptr::read_unaligned(buf[offset..].as_ptr() as *const Dirent) };
+            let name_start = offset + size_of::<Dirent>();
+            let name = &buf[name_start..name_start + dirent.name_len as usize];
+            entries.push(DirEntry {
+                name: String::from_utf8_lossy(name).into(),
+                is_dir: dirent.is_dir != 0,
+                size: dirent.size,
+                created: time(dirent.created),
+                modified: time(dirent.modified),
+                accessed: time(dirent.accessed),
+            });
+            offset += dirent.rec_len as usize;
+        }
+    }
+}
@@ -20,7 +20,7 @@ impl Stdin {
             let buf: &mut [u8] = &mut [0u8; 256];
             let ret = sys_read(0, buf);
 
-            if ret.is_none() {
+            if ret.is_err() {
                 continue;
             } else {
                 for i in 0..ret.unwrap() {
@@ -28,16 +28,16 @@ impl Stdin {
                     // handle backspace / enter... and finally return the string
                     match c {
                         13 => {
-                            sys_write(1, "\n".as_bytes());
+                            sys_write(1, "\n".as_bytes()).ok();
                             return line;
                         }
                         0x08 | 0x7F => {
                             line.pop();
-                            sys_write(1, "\x08\x20\x08".as_bytes());
+                            sys_write(1, "\x08\x20\x08".as_bytes()).ok();
                         }
                         _ => {
                             line.push(c as char);
-                            sys_write(1, &mut [c]);
+                            sys_write(1, &mut [c]).ok();
                         }
                     };
                 }
@@ -52,7 +52,7 @@ impl Stdout {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(1, s.as_bytes());
+        sys_write(1, s.as_bytes()).ok();
     }
 }
 
@@ -62,7 +62,7 @@ impl Stderr {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(2, s.as_bytes());
+        sys_write(2, s.as_bytes()).ok();
     }
 }
 
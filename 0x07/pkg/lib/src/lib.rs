@@ -14,12 +14,18 @@ pub mod io;
 pub mod allocator;
 pub extern crate alloc;
 pub mod sync;
+#[cfg(any(feature = "brk_alloc", feature = "kernel_alloc"))]
+pub mod thread;
 
+mod env;
+mod fs;
 mod syscall;
 
 use core::fmt::*;
 
 pub use alloc::*;
+pub use env::*;
+pub use fs::*;
 pub use io::*;
 pub use syscall::*;
 
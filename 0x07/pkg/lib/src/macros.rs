@@ -6,7 +6,8 @@ use crate::errln;
 macro_rules! entry {
     ($fn:ident) => {
         #[unsafe(export_name = "_start")]
-        pub extern "C" fn __impl_start() {
+        pub extern "C" fn __impl_start(argc: usize, argv: usize, envc: usize, envp: usize) {
+            lib::init_args(argc, argv, envc, envp);
             let ret = $fn();
             // FIXME: after syscall, add lib::sys_exit(ret);
             lib::sys_exit(ret);
@@ -44,20 +44,20 @@ impl Semaphore {
 
     #[inline(always)]
     pub fn init(&self, value: usize) -> bool {
-        sys_new_sem(self.key, value)
+        sys_new_sem(self.key, value).is_ok()
     }
      #[inline(always)]
     pub fn remove(&self) -> bool {
-        sys_remove_sem(self.key)
+        sys_remove_sem(self.key).is_ok()
     }
 
     #[inline(always)]
     pub fn wait(&self) -> bool {
-        sys_sem_wait(self.key)
+        sys_sem_wait(self.key).is_ok()
     }
 
     pub fn signal(&self) -> bool {
-        sys_sem_signal(self.key)
+        sys_sem_signal(self.key).is_ok()
     }
 
 }
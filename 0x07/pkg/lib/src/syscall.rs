@@ -1,38 +1,52 @@
-use syscall_def::Syscall;
+use syscall_def::{errno::decode, Syscall};
+use alloc::{string::String, vec, vec::Vec};
+
+pub use syscall_def::signal::{SigMaskHow, Signal};
+pub use syscall_def::{
+    Errno, ProcInfo, ProcStatus, Rlimit, RlimitValue, Rusage, RusageWho, TraceMode, RLIM_INFINITY,
+    WNOHANG,
+};
+use syscall_def::signal::{SIG_DFL, SIG_IGN};
 
 #[inline(always)]
-pub fn sys_write(fd: u8, buf: &[u8]) -> Option<usize> {
-    let ret = syscall!(
+pub fn sys_write(fd: u8, buf: &[u8]) -> Result<usize, Errno> {
+    decode(syscall!(
         Syscall::Write,
         fd as u64,
         buf.as_ptr() as u64,
         buf.len() as u64
-    ) as isize;
-    if ret.is_negative() {
-        None
-    } else {
-        Some(ret as usize)
-    }
+    ))
 }
 
 #[inline(always)]
-pub fn sys_read(fd: u8, buf: &mut [u8]) -> Option<usize> {
-    let ret = syscall!(
+pub fn sys_read(fd: u8, buf: &mut [u8]) -> Result<usize, Errno> {
+    decode(syscall!(
         Syscall::Read,
         fd as u64,
         buf.as_ptr() as u64,
         buf.len() as u64
-    ) as isize;
-    if ret.is_negative() {
-        None
-    } else {
-        Some(ret as usize)
-    }
+    ))
 }
 
+/// Wait for the child `pid` to exit and return its exit code
 #[inline(always)]
-pub fn sys_wait_pid(pid: u16) -> isize {
-    syscall!(Syscall::WaitPid, pid as u64) as isize
+pub fn sys_wait_pid(pid: u16) -> Result<isize, Errno> {
+    sys_wait_child(Some(pid), 0).map(|child| child.map_or(0, |(_, status)| status))
+}
+
+/// Wait for the child `pid`, or any child if `None`, to exit and return its
+/// pid and exit code, `None` if `WNOHANG` is in `flags` and none exited yet
+#[inline(always)]
+pub fn sys_wait_child(pid: Option<u16>, flags: usize) -> Result<Option<(u16, isize)>, Errno> {
+    let pid = pid.map_or(-1, |pid| pid as isize);
+    let mut status: isize = 0;
+    let child = decode(syscall!(
+        Syscall::WaitPid,
+        pid as u64,
+        &mut status as *mut isize as u64,
+        flags as u64
+    ))?;
+    Ok((child != 0).then_some((child as u16, status)))
 }
 #[inline(always)]
 pub fn sys_list_app() {
@@ -44,19 +58,77 @@ pub fn sys_stat() {
     syscall!(Syscall::Stat);
 }
 
+/// Every process and thread, zombies included, by pid
+pub fn sys_list_proc() -> Result<Vec<ProcInfo>, Errno> {
+    let mut infos = vec![ProcInfo::default(); 16];
+    loop {
+        let count = decode(syscall!(
+            Syscall::ListProc,
+            infos.as_mut_ptr() as u64,
+            infos.len() as u64
+        ))?;
+        // processes may be created between the calls, ask again if so
+        if count <= infos.len() {
+            infos.truncate(count);
+            return Ok(infos);
+        }
+        infos.resize(count + 8, ProcInfo::default());
+    }
+}
+
 #[inline(always)]
-pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
-    syscall!(Syscall::Allocate, layout as *const _) as *mut u8
+pub fn sys_allocate(layout: &core::alloc::Layout) -> Result<*mut u8, Errno> {
+    decode(syscall!(Syscall::Allocate, layout.size(), layout.align())).map(|ptr| ptr as *mut u8)
 }
 
 #[inline(always)]
-pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> usize {
-    syscall!(Syscall::Deallocate, ptr, layout as *const _)
+pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Deallocate, ptr, layout.size(), layout.align())).map(|_| ())
 }
 
+/// Join `strs` into the NUL-terminated block spawn and exec expect
+fn str_block(strs: &[&str]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for s in strs {
+        block.extend_from_slice(s.as_bytes());
+        block.push(0);
+    }
+    block
+}
+
+/// Spawn the program at `path`, `args` usually starts with the path itself
+/// and `env` holds `KEY=VALUE` strings
 #[inline(always)]
-pub fn sys_spawn(path: &str) -> u16 {
-    syscall!(Syscall::Spawn, path.as_ptr() as u64, path.len() as u64) as u16
+pub fn sys_spawn(path: &str, args: &[&str], env: &[&str]) -> Result<u16, Errno> {
+    let (args, env) = (str_block(args), str_block(env));
+    decode(syscall!(
+        Syscall::Spawn,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        args.as_ptr() as u64,
+        args.len() as u64,
+        env.as_ptr() as u64,
+        env.len() as u64
+    ))
+    .map(|pid| pid as u16)
+}
+
+/// Replace the current process image, only returns on failure
+#[inline(always)]
+pub fn sys_exec(path: &str, args: &[&str], env: &[&str]) -> Errno {
+    let (args, env) = (str_block(args), str_block(env));
+    match decode(syscall!(
+        Syscall::Exec,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        args.as_ptr() as u64,
+        args.len() as u64,
+        env.as_ptr() as u64,
+        env.len() as u64
+    )) {
+        Err(err) => err,
+        Ok(_) => unreachable!("exec returned without error"),
+    }
 }
 
 #[inline(always)]
@@ -74,56 +146,323 @@ pub fn sys_time() -> u64 {
     syscall!(Syscall::Time) as u64
 }
 #[inline(always)]
-pub fn sys_fork() -> u16 {
-    syscall!(Syscall::Fork) as u16
+pub fn sys_fork() -> Result<u16, Errno> {
+    decode(syscall!(Syscall::Fork)).map(|pid| pid as u16)
+}
+
+/// Start a thread of the current process at `entry(arg)`, running on the
+/// stack below `stack_top`, returns its tid
+#[inline(always)]
+pub fn sys_thread_create(entry: extern "C" fn(usize) -> !, arg: usize, stack_top: usize) -> Result<u16, Errno> {
+    decode(syscall!(Syscall::ThreadCreate, entry as usize, arg, stack_top)).map(|tid| tid as u16)
+}
+
+/// Wait for the thread `tid` to exit and return its exit code
+#[inline(always)]
+pub fn sys_thread_join(tid: u16) -> Result<isize, Errno> {
+    let mut status: isize = 0;
+    decode(syscall!(Syscall::ThreadJoin, tid as u64, &mut status as *mut isize))?;
+    Ok(status)
+}
+
+/// Exit the current thread, the main thread takes the whole process along
+#[inline(always)]
+pub fn sys_thread_exit(ret: isize) -> ! {
+    syscall!(Syscall::ThreadExit, ret as u64);
+    unreachable!("This thread should be terminated by now.")
 }
 
 use core::time::Duration;
 pub fn sleep(millisecs: i64) {
-    let start = Duration::from_secs(sys_time());
-    let dur = Duration::from_secs(millisecs as u64);
-    let mut current = start;
-    while current - start < dur {
-        current = Duration::from_secs(sys_time());
+    sys_sleep(Duration::from_millis(millisecs.max(0) as u64));
+}
+
+/// Block for at least `dur`, without using the CPU
+#[inline(always)]
+pub fn sys_sleep(dur: Duration) {
+    syscall!(Syscall::Sleep, dur.as_nanos().min(u64::MAX as u128) as u64);
+}
+
+/// Let other processes run before coming back
+#[inline(always)]
+pub fn sys_yield() {
+    syscall!(Syscall::Yield);
+}
+#[inline(always)]
+pub fn sys_new_sem(key: u32, value: usize) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Sem, 0, key as usize, value)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_remove_sem(key: u32) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Sem, 1, key as usize)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_sem_signal(key: u32) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Sem, 2, key as usize)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_sem_wait(key: u32) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Sem, 3, key as usize)).map(|_| ())
+}
+/// Fill `buf` with `Dirent` records of the directory `path` from the
+/// `start`-th entry on, returns the bytes written, 0 past the last entry
+///
+/// see `read_dir` for the parsed entries
+#[inline(always)]
+pub fn sys_get_dents(path: &str, buf: &mut [u8], start: usize) -> Result<usize, Errno> {
+    decode(syscall!(
+        Syscall::GetDents,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64,
+        start as u64
+    ))
+}
+#[inline(always)]
+pub fn sys_open_file(path: &str) -> Result<u8, Errno> {
+    decode(syscall!(Syscall::OpenFile, path.as_ptr() as u64, path.len() as u64)).map(|fd| fd as u8)
+}
+
+#[inline(always)]
+pub fn sys_close_file(fd: u8) -> Result<(), Errno> {
+    decode(syscall!(Syscall::CloseFile, fd as u64)).map(|_| ())
+}
+#[inline(always)]
+pub fn sys_brk(addr: Option<usize>) -> Result<usize, Errno> {
+    decode(syscall!(Syscall::Brk, addr.unwrap_or(0)))
+}
+
+/// Set the trace mode of the current process (`pid` 0) or of one of its
+/// descendants, processes spawned or forked later inherit it
+#[inline(always)]
+pub fn sys_trace(pid: u16, mode: TraceMode) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Trace, 0, pid as u64, mode as u64)).map(|_| ())
+}
+
+/// Drain the kernel trace buffer into `buf`
+#[inline(always)]
+pub fn sys_read_trace(buf: &mut [u8]) -> Result<usize, Errno> {
+    decode(syscall!(Syscall::Trace, 1, buf.as_ptr() as u64, buf.len() as u64))
+}
+
+/// Read a value of unknown length, `read` returns the full length and
+/// fills as much of the buffer as fits
+fn read_sized(mut read: impl FnMut(&mut [u8]) -> Result<usize, Errno>) -> Result<Vec<u8>, Errno> {
+    let mut buf = vec![0u8; 64];
+    loop {
+        let len = read(&mut buf)?;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+        buf.resize(len, 0);
     }
 }
+
+/// Current value of the environment variable `key`
+#[inline(always)]
+pub fn sys_get_env(key: &str) -> Option<String> {
+    let buf = read_sized(|buf| {
+        decode(syscall!(
+            Syscall::GetEnv,
+            key.as_ptr() as u64,
+            key.len() as u64,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64
+        ))
+    })
+    .ok()?;
+    String::from_utf8(buf).ok()
+}
+
+/// Set an environment variable, programs spawned afterwards inherit it
+#[inline(always)]
+pub fn sys_set_env(key: &str, val: &str) -> Result<(), Errno> {
+    decode(syscall!(
+        Syscall::SetEnv,
+        key.as_ptr() as u64,
+        key.len() as u64,
+        val.as_ptr() as u64,
+        val.len() as u64
+    ))
+    .map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_unset_env(key: &str) -> Result<(), Errno> {
+    decode(syscall!(Syscall::UnsetEnv, key.as_ptr() as u64, key.len() as u64)).map(|_| ())
+}
+
+/// Current environment as `(key, value)` pairs, unlike `env()` this
+/// includes the changes made since the process started
+#[inline(always)]
+pub fn sys_list_env() -> Result<Vec<(String, String)>, Errno> {
+    let buf = read_sized(|buf| {
+        decode(syscall!(
+            Syscall::ListEnv,
+            buf.as_mut_ptr() as u64,
+            buf.len() as u64
+        ))
+    })?;
+    Ok(String::from_utf8_lossy(&buf)
+        .split_terminator('\0')
+        .map(|var| {
+            let (key, val) = var.split_once('=').unwrap_or((var, ""));
+            (key.into(), val.into())
+        })
+        .collect())
+}
+
+/// Send `sig` to `pid`, a process may signal itself, its descendants and
+/// the processes of its session
+#[inline(always)]
+pub fn sys_kill(pid: u16, sig: Signal) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Kill, pid as u64, sig as u64)).map(|_| ())
+}
+
+/// Send `sig` to every process of the group `pgid`
+#[inline(always)]
+pub fn sys_kill_group(pgid: u16, sig: Signal) -> Result<(), Errno> {
+    decode(syscall!(Syscall::Kill, (pgid as i64).wrapping_neg() as u64, sig as u64)).map(|_| ())
+}
+
+/// Move `pid` (0 for the current process) into the group `pgid`
+/// (0 for a new group led by `pid`)
+#[inline(always)]
+pub fn sys_set_pgid(pid: u16, pgid: u16) -> Result<(), Errno> {
+    decode(syscall!(Syscall::SetPgid, pid as u64, pgid as u64)).map(|_| ())
+}
+
 #[inline(always)]
-pub fn sys_new_sem(key: u32, value: usize) -> bool {
-    syscall!(Syscall::Sem, 0, key as usize, value) == 0
+pub fn sys_get_pgid(pid: u16) -> Result<u16, Errno> {
+    decode(syscall!(Syscall::GetPgid, pid as u64)).map(|pgid| pgid as u16)
 }
 
+/// Start a new session led by the current process, returns its id
 #[inline(always)]
-pub fn sys_remove_sem(key: u32) -> bool {
-    syscall!(Syscall::Sem, 1, key as usize) == 0
+pub fn sys_set_sid() -> Result<u16, Errno> {
+    decode(syscall!(Syscall::SetSid)).map(|sid| sid as u16)
 }
 
 #[inline(always)]
-pub fn sys_sem_signal(key: u32) -> bool {
-    syscall!(Syscall::Sem, 2, key as usize) == 0
+pub fn sys_get_sid(pid: u16) -> Result<u16, Errno> {
+    decode(syscall!(Syscall::GetSid, pid as u64)).map(|sid| sid as u16)
 }
 
+/// Set the nice value of `pid` (0 for the current process), from -20 (most
+/// favoured) to 19, out of range values are clamped
+///
+/// `pid` must be the current process or a descendant, and the value can
+/// only be raised, `PermissionDenied` otherwise
 #[inline(always)]
-pub fn sys_sem_wait(key: u32) -> bool {
-    syscall!(Syscall::Sem, 3, key as usize) == 0
+pub fn sys_set_priority(pid: u16, nice: i8) -> Result<(), Errno> {
+    decode(syscall!(Syscall::SetPriority, pid as u64, nice as i64 as u64)).map(|_| ())
 }
+
+/// Nice value of `pid` (0 for the current process)
 #[inline(always)]
-pub fn sys_list_dir(path: &str) {
-    syscall!(Syscall::ListDir, path.as_ptr() as u64, path.len() as u64);
+pub fn sys_get_priority(pid: u16) -> Result<i8, Errno> {
+    decode(syscall!(Syscall::GetPriority, pid as u64)).map(|prio| (20 - prio as isize) as i8)
 }
+
+/// Soft and hard limits on `res` of the current process, `RLIM_INFINITY`
+/// if there is none
+#[inline(always)]
+pub fn sys_get_rlimit(res: Rlimit) -> Result<RlimitValue, Errno> {
+    let mut value = RlimitValue::INFINITY;
+    decode(syscall!(Syscall::GetRlimit, res as u64, &mut value as *mut RlimitValue as u64))?;
+    Ok(value)
+}
+
+/// Usage of the current process or thread, or of the children waited for
+#[inline(always)]
+pub fn sys_get_rusage(who: RusageWho) -> Result<Rusage, Errno> {
+    let mut usage = Rusage::default();
+    decode(syscall!(Syscall::GetRusage, who.as_arg() as u64, &mut usage as *mut Rusage as u64))?;
+    Ok(usage)
+}
+
+/// Limit `res` of the current process, children started later inherit it
+///
+/// the hard limit can only be lowered, the soft one raised up to it
+#[inline(always)]
+pub fn sys_set_rlimit(res: Rlimit, value: RlimitValue) -> Result<(), Errno> {
+    decode(syscall!(Syscall::SetRlimit, res as u64, &value as *const RlimitValue as u64)).map(|_| ())
+}
+
+/// Add `inc` to the nice value of the current process, returns the new one
 #[inline(always)]
-pub fn sys_open_file(path: &str) -> u8 {
-    syscall!(Syscall::OpenFile, path.as_ptr() as u64, path.len() as u64) as u8
+pub fn sys_nice(inc: i8) -> Result<i8, Errno> {
+    let nice = sys_get_priority(0)?.saturating_add(inc);
+    sys_set_priority(0, nice)?;
+    sys_get_priority(0)
 }
 
+/// Give the console to the group `pgid`, only it may read stdin
 #[inline(always)]
-pub fn sys_close_file(fd: u8) -> bool {
-    syscall!(Syscall::CloseFile, fd as u64) == 0
+pub fn sys_set_foreground(pgid: u16) -> Result<(), Errno> {
+    decode(syscall!(Syscall::SetForeground, pgid as u64)).map(|_| ())
 }
+
+/// Group owning the console, if any
 #[inline(always)]
-pub fn sys_brk(addr: Option<usize>) -> Option<usize> {
-    const BRK_FAILED: usize = !0;
-    match syscall!(Syscall::Brk, addr.unwrap_or(0)) {
-        BRK_FAILED => None,
-        ret => Some(ret),
+pub fn sys_get_foreground() -> Option<u16> {
+    match decode(syscall!(Syscall::GetForeground)) {
+        Ok(0) | Err(_) => None,
+        Ok(pgid) => Some(pgid as u16),
     }
-}
\ No newline at end of file
+}
+
+/// What the process does when it receives a signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigHandler {
+    Default,
+    Ignore,
+    Handler(extern "C" fn(sig: usize)),
+}
+
+// handlers return here, the kernel then restores the interrupted context
+core::arch::global_asm!(
+    ".global __sigreturn_trampoline",
+    "__sigreturn_trampoline:",
+    "mov rax, {sigreturn}",
+    "syscall",
+    "ud2",
+    sigreturn = const Syscall::SigReturn as usize,
+);
+
+unsafe extern "C" {
+    fn __sigreturn_trampoline();
+}
+
+/// Set the handler of `sig`, returns the previous one
+#[inline(always)]
+pub fn sys_signal(sig: Signal, handler: SigHandler) -> Result<SigHandler, Errno> {
+    let raw = match handler {
+        SigHandler::Default => SIG_DFL,
+        SigHandler::Ignore => SIG_IGN,
+        SigHandler::Handler(handler) => handler as usize,
+    };
+    let old = decode(syscall!(
+        Syscall::SigAction,
+        sig as u64,
+        raw as u64,
+        __sigreturn_trampoline as usize as u64
+    ))?;
+    Ok(match old {
+        SIG_DFL => SigHandler::Default,
+        SIG_IGN => SigHandler::Ignore,
+        old => SigHandler::Handler(unsafe { core::mem::transmute::<usize, extern "C" fn(usize)>(old) }),
+    })
+}
+
+/// Change the blocked signals, `set` is a mask of `Signal::mask()`,
+/// returns the previous mask
+#[inline(always)]
+pub fn sys_sigprocmask(how: SigMaskHow, set: u64) -> Result<u64, Errno> {
+    decode(syscall!(Syscall::SigProcMask, how as u64, set)).map(|old| old as u64)
+}
@@ -0,0 +1,117 @@
+//! Threads of the current process
+//!
+//! A thread shares the address space of the process and gets its own stack,
+//! carved out of the program break. Stacks of joined threads are kept for
+//! the next `spawn`, the heap of the allocator stays below all of them.
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::cell::UnsafeCell;
+
+use crate::sync::SpinLock;
+use crate::{sys_brk, sys_thread_create, sys_thread_exit, sys_thread_join, Errno};
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Multiple of the page size, so stacks do not share pages with the heap
+const STACK_SIZE: usize = 4 * PAGE_SIZE;
+
+struct StackPool {
+    lock: SpinLock,
+    free: UnsafeCell<Vec<usize>>,
+}
+
+unsafe impl Sync for StackPool {}
+
+static STACKS: StackPool = StackPool {
+    lock: SpinLock::new(),
+    free: UnsafeCell::new(Vec::new()),
+};
+
+impl StackPool {
+    /// Bottom of an unused stack
+    fn take(&self) -> Result<usize, Errno> {
+        self.lock.acquire();
+        let free = unsafe { &mut *self.free.get() };
+        let stack = match free.pop() {
+            Some(stack) => Ok(stack),
+            None => sys_brk(None).and_then(|end| {
+                let base = (end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+                sys_brk(Some(base + STACK_SIZE)).map(|_| base)
+            }),
+        };
+        self.lock.release();
+        stack
+    }
+
+    fn give_back(&self, stack: usize) {
+        self.lock.acquire();
+        unsafe { &mut *self.free.get() }.push(stack);
+        self.lock.release();
+    }
+}
+
+/// Where a thread leaves its result for `join`
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+pub struct JoinHandle<T> {
+    tid: u16,
+    stack: usize,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn tid(&self) -> u16 {
+        self.tid
+    }
+
+    /// Wait for the thread to finish and take its result
+    ///
+    /// fails with `Interrupted` if the thread was killed before returning
+    pub fn join(self) -> Result<T, Errno> {
+        sys_thread_join(self.tid)?;
+        STACKS.give_back(self.stack);
+        // the thread is gone, nobody else touches the packet
+        unsafe { (*self.packet.result.get()).take() }.ok_or(Errno::Interrupted)
+    }
+}
+
+type ThreadMain = Box<dyn FnOnce() + Send>;
+
+extern "C" fn thread_start(arg: usize) -> ! {
+    let main = unsafe { Box::from_raw(arg as *mut ThreadMain) };
+    main();
+    sys_thread_exit(0)
+}
+
+/// Run `f` in a new thread of the current process
+///
+/// dropping the `JoinHandle` detaches the thread, its stack is not reused
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: UnsafeCell::new(None),
+    });
+    let their_packet = packet.clone();
+    let main: ThreadMain = Box::new(move || {
+        let result = f();
+        unsafe { *their_packet.result.get() = Some(result) };
+    });
+    let arg = Box::into_raw(Box::new(main));
+
+    let stack = STACKS.take().expect("Failed to allocate thread stack");
+    match sys_thread_create(thread_start, arg as usize, stack + STACK_SIZE) {
+        Ok(tid) => JoinHandle { tid, stack, packet },
+        Err(err) => {
+            drop(unsafe { Box::from_raw(arg) });
+            STACKS.give_back(stack);
+            panic!("Failed to spawn thread: {}", err)
+        }
+    }
+}
@@ -8,27 +8,13 @@ pub trait Read {
 
     /// Read all bytes until EOF in this source, placing them into `buf`.
     fn read_all(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
-        let mut start_len = buf.len();
-        let mut readed_byt = 0;
+        let start_len = buf.len();
+        let mut chunk = [0u8; 1024];
         loop {
-            // FIXME: read data into the buffer
-            //      - extend the buffer if it's not big enough
-            //      - break if the read returns 0 or Err
-            //      - update the length of the buffer if data was read
-            let one_buf = &mut [0u8; 1024];
-            match self.read(one_buf) {
-                Ok(size) =>{
-                    readed_byt += size;
-                    buf.append(&mut one_buf.to_vec());
-                    if size == 0 {
-                        return Ok(readed_byt);
-                    }
-                }
-                _ =>{
-                    return Err(FsError::FileNotFound);
-                }
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start_len),
+                size => buf.extend_from_slice(&chunk[..size]),
             }
-
         }
     }
 }
@@ -73,3 +59,37 @@ pub trait Seek {
 pub trait FileIO: Read + Write + Seek {}
 
 impl<T: Read + Write + Seek> FileIO for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out `data` in chunks of at most `chunk` bytes
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let count = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data = &self.data[count..];
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn test_read_all() {
+        let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let mut reader = Chunked {
+            data: &data,
+            chunk: 700,
+        };
+
+        let mut buf = vec![0xaa];
+        assert_eq!(reader.read_all(&mut buf).unwrap(), data.len());
+        assert_eq!(buf[0], 0xaa);
+        assert_eq!(&buf[1..], &data[..]);
+    }
+}
@@ -50,3 +50,139 @@ impl core::fmt::Debug for Mount {
             .finish()
     }
 }
+
+/// File systems mounted on top of a root file system
+///
+/// A path is handled by the mount whose mount point is its longest prefix
+/// ending on a path component, e.g. `/proc/1` goes to `/proc` but
+/// `/process` stays on `/`.
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    pub fn new(root: Mount) -> Self {
+        Self {
+            mounts: vec![root],
+        }
+    }
+
+    /// Mount `mount` on top of the existing ones
+    pub fn mount(&mut self, mount: Mount) {
+        self.mounts.push(mount);
+    }
+
+    fn resolve(&self, path: &str) -> Result<&Mount> {
+        self.mounts
+            .iter()
+            .filter(|mount| covers(&mount.mount_point, path))
+            .max_by_key(|mount| mount.mount_point.len())
+            .ok_or(FsError::FileNotFound)
+    }
+
+    /// Mount points directly inside the directory `path`
+    fn mounted_in(&self, path: &str) -> Vec<Metadata> {
+        let dir = path.trim_end_matches(PATH_SEPARATOR);
+        self.mounts
+            .iter()
+            .filter_map(|mount| {
+                let point = mount.mount_point.trim_end_matches(PATH_SEPARATOR);
+                let (parent, name) = point.rsplit_once(PATH_SEPARATOR)?;
+                (parent == dir && !name.is_empty()).then(|| {
+                    Metadata::new(name.to_owned(), FileType::Directory, 0, None, None, None)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether `path` is `mount_point` or lies below it
+fn covers(mount_point: &str, path: &str) -> bool {
+    let point = mount_point.trim_end_matches(PATH_SEPARATOR);
+    path.strip_prefix(point)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(PATH_SEPARATOR))
+}
+
+impl FileSystem for MountTable {
+    fn read_dir(&self, path: &str) -> Result<Box<dyn Iterator<Item = Metadata> + Send>> {
+        let entries = self.resolve(path)?.read_dir(path)?;
+        Ok(Box::new(entries.chain(self.mounted_in(path))))
+    }
+
+    fn open_file(&self, path: &str) -> Result<FileHandle> {
+        self.resolve(path)?.open_file(path)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        self.resolve(path)?.metadata(path)
+    }
+
+    fn exists(&self, path: &str) -> Result<bool> {
+        self.resolve(path)?.exists(path)
+    }
+}
+
+impl core::fmt::Debug for MountTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.mounts.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports the path it was asked about as the name of the entry
+    #[derive(Debug)]
+    struct Echo(&'static str);
+
+    impl Echo {
+        fn entry(&self, path: &str) -> Metadata {
+            let name = format!("{}:{}", self.0, path);
+            Metadata::new(name, FileType::File, 0, None, None, None)
+        }
+    }
+
+    impl FileSystem for Echo {
+        fn read_dir(&self, path: &str) -> Result<Box<dyn Iterator<Item = Metadata> + Send>> {
+            Ok(Box::new(core::iter::once(self.entry(path))))
+        }
+
+        fn open_file(&self, _path: &str) -> Result<FileHandle> {
+            Err(FsError::NotSupported)
+        }
+
+        fn metadata(&self, path: &str) -> Result<Metadata> {
+            Ok(self.entry(path))
+        }
+
+        fn exists(&self, _path: &str) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    fn table() -> MountTable {
+        let mut table = MountTable::new(Mount::new(Box::new(Echo("root")), "/".into()));
+        table.mount(Mount::new(Box::new(Echo("proc")), "/proc".into()));
+        table
+    }
+
+    #[test]
+    fn test_resolve() {
+        let table = table();
+        assert_eq!(table.metadata("/proc/1/maps").unwrap().name, "proc:/1/maps");
+        assert_eq!(table.metadata("/proc").unwrap().name, "proc:");
+        assert_eq!(table.metadata("/process").unwrap().name, "root:process");
+        assert_eq!(table.metadata("/APP/SH").unwrap().name, "root:APP/SH");
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let table = table();
+        let names: Vec<String> = table.read_dir("/").unwrap().map(|m| m.name).collect();
+        assert_eq!(names, ["root:", "proc"]);
+
+        let names: Vec<String> = table.read_dir("/proc").unwrap().map(|m| m.name).collect();
+        assert_eq!(names, ["proc:"]);
+    }
+}
@@ -43,35 +43,35 @@ impl Read for File {
         //      - use `self.handle.cluster_to_sector` to convert cluster to sector
         //      - update `self.offset` after reading
         //      - update `self.cluster` with FAT if necessary
-        let bytes_per_sec = self.handle.bpb.bytes_per_sector();
-        let sec_per_clus = self.handle.bpb.sectors_per_cluster();
+        let length = self.length();
+        let bytes_per_sec = self.handle.bpb.bytes_per_sector() as usize;
+        let cluster_size = bytes_per_sec * self.handle.bpb.sectors_per_cluster() as usize;
         let mut block = Block::default();
-        let mut sector_num = self.offset / bytes_per_sec as usize; 
-        let mut bytes_num = self.offset % bytes_per_sec as usize;
-        let mut current_offset = self.handle.cluster_to_sector(&self.current_cluster) + sector_num;
-        let mut space = buf.len();
-        let mut read:usize = 0;
-        while  space > 0 || self.offset == self.entry.size as usize {
-            self.handle.inner.read_block(current_offset,&mut block)?;
-            let need_to_read = min(space,
-                                        min(bytes_per_sec as usize - bytes_num, 
-                                                self.entry.size as usize - self.offset)
-                                            );
-            space -= need_to_read;
-            self.offset += need_to_read;
-            buf[read .. read+need_to_read].copy_from_slice(&block[bytes_num .. bytes_num+need_to_read]);
-            read += need_to_read;
-            bytes_num = 0;
-            sector_num = match sector_num+1 {
-                sec_per_clus=> {
-                    self.current_cluster = self.handle.get_next_cluster(&self.current_cluster)?;
-                    0
-                }
-                _ => sector_num + 1,
+        let mut read = 0;
+
+        while read < buf.len() && self.offset < length {
+            let cluster_offset = self.offset % cluster_size;
+            let sector = self.handle.cluster_to_sector(&self.current_cluster)
+                + cluster_offset / bytes_per_sec;
+            let sector_offset = cluster_offset % bytes_per_sec;
+            self.handle.inner.read_block(sector, &mut block)?;
+
+            let count = min(
+                buf.len() - read,
+                min(bytes_per_sec - sector_offset, length - self.offset),
+            );
+            buf[read..read + count]
+                .copy_from_slice(&block[sector_offset..sector_offset + count]);
+            read += count;
+            self.offset += count;
+
+            // follow the FAT once the current cluster is used up
+            if self.offset % cluster_size == 0 && self.offset < length {
+                self.current_cluster = self.handle.get_next_cluster(&self.current_cluster)?;
             }
-            
         }
-        return Ok(read);
+
+        Ok(read)
     }
 }
 
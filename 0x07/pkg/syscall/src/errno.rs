@@ -0,0 +1,82 @@
+use num_enum::FromPrimitive;
+
+/// Errors returned by syscalls
+///
+/// the kernel returns them negated in `rax`, so every value in
+/// `[-MAX_ERRNO, -1]` is an error, and the numbers follow Linux
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum Errno {
+    PermissionDenied = 1,
+    NotFound = 2,
+    NoSuchProcess = 3,
+    Interrupted = 4,
+    IoError = 5,
+    ArgumentListTooLong = 7,
+    NotExecutable = 8,
+    BadFd = 9,
+    NoChild = 10,
+    WouldBlock = 11,
+    NoMemory = 12,
+    BadAddress = 14,
+    Busy = 16,
+    Exists = 17,
+    NotADirectory = 20,
+    IsADirectory = 21,
+    InvalidArgument = 22,
+    TooManyFiles = 24,
+    NoSpace = 28,
+    NameTooLong = 36,
+    NotSupported = 38,
+    #[num_enum(default)]
+    Unknown = 4095,
+}
+
+pub const MAX_ERRNO: usize = 4095;
+
+/// Encode a syscall result into the raw value returned in `rax`
+pub fn encode(ret: Result<usize, Errno>) -> usize {
+    match ret {
+        Ok(value) => value,
+        Err(errno) => (errno as usize).wrapping_neg(),
+    }
+}
+
+/// Decode the raw value returned in `rax`
+pub fn decode(ret: usize) -> Result<usize, Errno> {
+    if ret >= MAX_ERRNO.wrapping_neg() {
+        Err(Errno::from(ret.wrapping_neg()))
+    } else {
+        Ok(ret)
+    }
+}
+
+impl core::fmt::Display for Errno {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let msg = match self {
+            Errno::PermissionDenied => "Permission denied",
+            Errno::NotFound => "No such file or directory",
+            Errno::NoSuchProcess => "No such process",
+            Errno::Interrupted => "Interrupted",
+            Errno::IoError => "I/O error",
+            Errno::ArgumentListTooLong => "Argument list too long",
+            Errno::NotExecutable => "Not an executable",
+            Errno::BadFd => "Bad file descriptor",
+            Errno::NoChild => "No child process",
+            Errno::WouldBlock => "Resource temporarily unavailable",
+            Errno::NoMemory => "Out of memory",
+            Errno::BadAddress => "Bad address",
+            Errno::Busy => "Resource busy",
+            Errno::Exists => "Already exists",
+            Errno::NotADirectory => "Not a directory",
+            Errno::IsADirectory => "Is a directory",
+            Errno::InvalidArgument => "Invalid argument",
+            Errno::TooManyFiles => "Too many open files",
+            Errno::NoSpace => "No space left",
+            Errno::NameTooLong => "File name too long",
+            Errno::NotSupported => "Function not implemented",
+            Errno::Unknown => "Unknown error",
+        };
+        f.write_str(msg)
+    }
+}
@@ -1,8 +1,13 @@
 #![no_std]
 
-use num_enum::FromPrimitive;
+use num_enum::{FromPrimitive, TryFromPrimitive};
 
+pub mod errno;
 pub mod macros;
+pub mod signal;
+
+pub use errno::Errno;
+pub use signal::Signal;
 
 #[repr(usize)]
 #[derive(Clone, Debug, FromPrimitive)]
@@ -14,16 +19,44 @@ pub enum Syscall {
     Sem =41,
     Fork = 58,
 
+    SigAction = 13,
+    SigProcMask = 14,
+    SigReturn = 15,
+    Yield = 24,
+    Sleep = 35,
+
+    Exec = 57,
     Spawn = 59,
     Exit = 60,
+    Kill = 62,
     WaitPid = 64,
+    SetPgid = 109,
+    SetSid = 112,
+    GetPgid = 121,
+    GetSid = 124,
+    GetRlimit = 97,
+    GetRusage = 98,
+    GetPriority = 140,
+    SetPriority = 141,
+    SetRlimit = 160,
 
     ListApp = 65531,
     Stat = 65532,
     Allocate = 65533,
     Deallocate = 65534,
     Time = 65529,
-    ListDir=42,
+    ListProc = 65530,
+    Trace = 65528,
+    ThreadCreate = 65517,
+    ThreadJoin = 65518,
+    ThreadExit = 65519,
+    GetEnv = 65520,
+    SetEnv = 65521,
+    UnsetEnv = 65522,
+    ListEnv = 65523,
+    SetForeground = 65524,
+    GetForeground = 65525,
+    GetDents = 78,
     OpenFile = 43,
     CloseFile = 44,
     Brk = 45,
@@ -31,3 +64,259 @@ pub enum Syscall {
     Unknown = 65535
     
 }
+
+/// Resources limited by `GetRlimit` and `SetRlimit`, the numbers follow
+/// Linux
+///
+/// heap and stack are in bytes, resident memory in pages
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum Rlimit {
+    CpuTicks = 0,
+    Heap = 2,
+    Stack = 3,
+    ResidentPages = 5,
+    OpenFiles = 7,
+}
+
+/// `flags` of `WaitPid`: return 0 instead of blocking if no child exited
+pub const WNOHANG: usize = 1;
+
+/// No limit on the resource
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// Limits on a resource, read by `GetRlimit` and set by `SetRlimit`
+///
+/// the soft limit is the one enforced, a process may raise it up to the
+/// hard limit and lower the hard limit, but never raise it again
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RlimitValue {
+    pub soft: usize,
+    pub hard: usize,
+}
+
+impl RlimitValue {
+    pub const INFINITY: Self = Self {
+        soft: RLIM_INFINITY,
+        hard: RLIM_INFINITY,
+    };
+}
+
+/// Whose usage `GetRusage` reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RusageWho {
+    /// all threads of the calling process
+    Process,
+    /// the calling thread
+    Thread,
+    /// the children that exited and were waited for, and theirs
+    Children,
+}
+
+impl RusageWho {
+    /// The syscall argument, the numbers follow Linux, `Children` is -1
+    pub const fn as_arg(self) -> usize {
+        match self {
+            RusageWho::Process => 0,
+            RusageWho::Thread => 1,
+            RusageWho::Children => usize::MAX,
+        }
+    }
+}
+
+impl TryFrom<usize> for RusageWho {
+    type Error = usize;
+
+    fn try_from(arg: usize) -> Result<Self, usize> {
+        match arg {
+            0 => Ok(RusageWho::Process),
+            1 => Ok(RusageWho::Thread),
+            usize::MAX => Ok(RusageWho::Children),
+            _ => Err(arg),
+        }
+    }
+}
+
+/// Resource usage reported by `GetRusage`, times are in timer ticks
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rusage {
+    pub user_ticks: u64,
+    pub system_ticks: u64,
+    /// switched out while waiting for something
+    pub voluntary_switches: u64,
+    /// switched out while still runnable, at the end of a time slice or on
+    /// yield
+    pub involuntary_switches: u64,
+    /// page faults served by growing the stack or copying on write
+    pub page_faults: u64,
+    pub syscalls: u64,
+}
+
+impl core::ops::AddAssign for Rusage {
+    fn add_assign(&mut self, other: Self) {
+        self.user_ticks += other.user_ticks;
+        self.system_ticks += other.system_ticks;
+        self.voluntary_switches += other.voluntary_switches;
+        self.involuntary_switches += other.involuntary_switches;
+        self.page_faults += other.page_faults;
+        self.syscalls += other.syscalls;
+    }
+}
+
+impl core::ops::Sub for Rusage {
+    type Output = Self;
+
+    /// What happened between two readings, `other` being the earlier one
+    fn sub(self, other: Self) -> Self {
+        Self {
+            user_ticks: self.user_ticks - other.user_ticks,
+            system_ticks: self.system_ticks - other.system_ticks,
+            voluntary_switches: self.voluntary_switches - other.voluntary_switches,
+            involuntary_switches: self.involuntary_switches - other.involuntary_switches,
+            page_faults: self.page_faults - other.page_faults,
+            syscalls: self.syscalls - other.syscalls,
+        }
+    }
+}
+
+/// State of a process in a `ProcInfo`
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum ProcStatus {
+    Running = 0,
+    Ready = 1,
+    Blocked = 2,
+    Stopped = 3,
+    /// exited, waiting to be reaped by its parent
+    Dead = 4,
+}
+
+/// Bytes of the name kept in a `ProcInfo`, longer names are cut
+pub const PROC_NAME_LEN: usize = 32;
+
+/// A process or thread, as listed by `ListProc`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ProcInfo {
+    pub pid: u16,
+    /// 0 for the kernel
+    pub ppid: u16,
+    /// the process a thread belongs to, `pid` for processes
+    pub tgid: u16,
+    pub pgid: u16,
+    pub status: u8,
+    pub nice: i8,
+    pub name_len: u8,
+    _pad: u8,
+    pub name: [u8; PROC_NAME_LEN],
+    _pad2: [u8; 4],
+    pub user_ticks: u64,
+    pub system_ticks: u64,
+    /// resident memory in bytes, 0 once a dead process is released
+    pub memory: u64,
+    /// only meaningful for dead processes
+    pub exit_code: i64,
+}
+
+// copied to user space as bytes, there must be no padding left uninitialized
+const _: () = assert!(core::mem::size_of::<ProcInfo>() == 80);
+
+impl Default for ProcInfo {
+    fn default() -> Self {
+        Self {
+            pid: 0,
+            ppid: 0,
+            tgid: 0,
+            pgid: 0,
+            status: ProcStatus::Dead as u8,
+            nice: 0,
+            name_len: 0,
+            _pad: 0,
+            name: [0; PROC_NAME_LEN],
+            _pad2: [0; 4],
+            user_ticks: 0,
+            system_ticks: 0,
+            memory: 0,
+            exit_code: 0,
+        }
+    }
+}
+
+impl ProcInfo {
+    /// Store `name`, cut to `PROC_NAME_LEN` bytes on a char boundary
+    pub fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(PROC_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        self.name_len = len as u8;
+    }
+
+    pub fn name(&self) -> &str {
+        let len = (self.name_len as usize).min(PROC_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    pub fn status(&self) -> Option<ProcStatus> {
+        ProcStatus::try_from(self.status).ok()
+    }
+
+    /// Exit code of a dead process
+    pub fn exit_code(&self) -> Option<i64> {
+        (self.status() == Some(ProcStatus::Dead)).then_some(self.exit_code)
+    }
+}
+
+/// Timestamp of a `Dirent` the file system does not keep
+pub const TIME_UNKNOWN: i64 = i64::MIN;
+
+/// Header of a directory entry written by `GetDents`, the name follows it
+/// and the next entry starts `rec_len` bytes after this one
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Dirent {
+    /// header, name and padding to 8 bytes
+    pub rec_len: u16,
+    pub name_len: u16,
+    pub is_dir: u8,
+    _pad: [u8; 3],
+    /// in bytes, 0 for directories
+    pub size: u64,
+    /// seconds since the Unix epoch, or `TIME_UNKNOWN`
+    pub created: i64,
+    pub modified: i64,
+    pub accessed: i64,
+}
+
+impl Dirent {
+    pub fn new(name_len: u16, is_dir: bool, size: u64, times: [i64; 3]) -> Self {
+        Self {
+            rec_len: Self::record_len(name_len as usize) as u16,
+            name_len,
+            is_dir: is_dir as u8,
+            _pad: [0; 3],
+            size,
+            created: times[0],
+            modified: times[1],
+            accessed: times[2],
+        }
+    }
+
+    /// Length of the record of an entry named with `name_len` bytes
+    pub const fn record_len(name_len: usize) -> usize {
+        (core::mem::size_of::<Self>() + name_len).next_multiple_of(8)
+    }
+}
+
+/// Where the syscalls of a traced process are logged
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, TryFromPrimitive)]
+pub enum TraceMode {
+    #[default]
+    Off = 0,
+    Console = 1,
+    Buffer = 2,
+}
@@ -0,0 +1,56 @@
+use num_enum::TryFromPrimitive;
+
+/// Signals understood by the kernel, the numbers follow Linux
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum Signal {
+    Hangup = 1,
+    Interrupt = 2,
+    Quit = 3,
+    Illegal = 4,
+    Abort = 6,
+    FloatingPoint = 8,
+    Kill = 9,
+    User1 = 10,
+    Segfault = 11,
+    User2 = 12,
+    Pipe = 13,
+    Alarm = 14,
+    Terminate = 15,
+    Child = 17,
+    Continue = 18,
+    Stop = 19,
+    TerminalStop = 20,
+    TerminalInput = 21,
+    CpuLimit = 24,
+}
+
+/// Number of signal slots, signal numbers are in `1..NSIG`
+pub const NSIG: usize = 32;
+
+/// Handler values of `SigAction`, other values are handler addresses
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// `how` of `SigProcMask`
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum SigMaskHow {
+    Block = 0,
+    Unblock = 1,
+    SetMask = 2,
+}
+
+impl Signal {
+    /// Bit of the signal in pending and blocked masks
+    #[inline]
+    pub const fn mask(self) -> u64 {
+        1 << self as usize
+    }
+
+    /// `Kill` and `Stop` can not be caught, blocked or ignored
+    #[inline]
+    pub const fn catchable(self) -> bool {
+        !matches!(self, Signal::Kill | Signal::Stop)
+    }
+}
@@ -13,6 +13,8 @@ parser.add_argument('-i', '--intdbg', action='store_true',
                     help='Enable interrupt output for qemu')
 parser.add_argument('-m', '--memory', default='96M',
                     help='Set memory size for qemu, default is 96M')
+parser.add_argument('-s', '--smp', default='4',
+                    help='Set cpu count for qemu, default is 4')
 parser.add_argument('-o', '--output', default='-nographic',
                     help='Set output for qemu, default is -nographic')
 parser.add_argument('-p', '--profile', type=str, choices=['release', 'debug'],
@@ -73,7 +75,7 @@ def execute_command(cmd: list, workdir: str | None = None, shell: bool = False)
     return prog.returncode
 
 
-def qemu(output: str = '-nographic', memory: str = '96M', debug: bool = False, intdbg: bool = False):
+def qemu(output: str = '-nographic', memory: str = '96M', smp: str = '4', debug: bool = False, intdbg: bool = False):
     qemu_exe = shutil.which('qemu-system-x86_64')
 
     # add optional path C:\Program Files\qemu for Windows
@@ -85,7 +87,7 @@ def qemu(output: str = '-nographic', memory: str = '96M', debug: bool = False, i
         raise Exception('qemu-system-x86_64 not found in PATH')
 
     qemu_args = [qemu_exe, '-bios', args.bios, '-net', 'none', *output.split(),
-                 '-m', memory, '-drive', 'format=raw,file=fat:esp', '-snapshot']
+                 '-m', memory, '-smp', smp, '-drive', 'format=raw,file=fat:esp', '-snapshot']
 
     if debug:
         qemu_args += ['-gdb', f'tcp:{args.debug_listen}', '-S']
@@ -202,10 +204,10 @@ def main():
     elif args.task == 'clean':
         clean()
     elif args.task == 'launch':
-        qemu(args.output, args.memory, args.debug, args.intdbg)
+        qemu(args.output, args.memory, args.smp, args.debug, args.intdbg)
     elif args.task == 'run':
         build()
-        qemu(args.output, args.memory, args.debug, args.intdbg)
+        qemu(args.output, args.memory, args.smp, args.debug, args.intdbg)
     elif args.task == 'clippy':
         clippy()
 